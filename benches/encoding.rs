@@ -0,0 +1,112 @@
+use apache_avro::Schema;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use mongodb::bson::{doc, from_document, Document};
+use mongodb::change_stream::event::UpdateDescription;
+use mstream::config::Compression;
+use mstream::diff;
+use mstream::encoding::avro::{encode, unknown_fields};
+use mstream::encoding::compression::compress;
+
+const RAW_SCHEMA: &str = r###"
+{
+    "type" : "record",
+    "name" : "Employee",
+    "fields" : [
+        { "name": "name" , "type" : "string" },
+        { "name": "age" , "type" : "int" },
+        { "name": "nickname", "type": ["null", "string"], "default": null },
+        { "name": "teams", "type": "array", "items": "string" },
+        { "name": "project", "type": {
+            "type": "record",
+            "name": "EmployeeProject",
+            "fields": [
+                { "name": "title", "type": "string" },
+                { "name": "rating", "type": "double" }
+            ]
+        }},
+        { "name": "is_active", "type": "boolean" },
+        { "name": "long_number", "type": "long" }
+    ]
+}
+"###;
+
+fn employee() -> Document {
+    doc! {
+        "name": "Jon Doe",
+        "age": 32,
+        "nickname": "JD",
+        "teams": ["team A", "team B", "team C"],
+        "project": { "title": "Awesome Project", "rating": 92.5_f64 },
+        "is_active": true,
+        "long_number": 100500_i64,
+        "additional_field": "foobar",
+    }
+}
+
+fn avro_encoding(c: &mut Criterion) {
+    let schema = Schema::parse_str(RAW_SCHEMA).unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("avro");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("encode", |b| {
+        b.iter(|| encode(black_box(employee()), black_box(schema.clone())).unwrap())
+    });
+    // the listener encodes on the blocking pool, this includes the hop to it and back
+    group.bench_function("encode_blocking", |b| {
+        b.iter(|| {
+            let (mongo_doc, schema) = (black_box(employee()), black_box(schema.clone()));
+            runtime
+                .block_on(async move {
+                    tokio::task::spawn_blocking(move || encode(mongo_doc, schema)).await
+                })
+                .unwrap()
+                .unwrap()
+        })
+    });
+    group.bench_function("unknown_fields", |b| {
+        let mongo_doc = employee();
+        b.iter(|| unknown_fields(black_box(&mongo_doc), black_box(&schema)).len())
+    });
+
+    group.finish();
+}
+
+fn compression(c: &mut Criterion) {
+    let schema = Schema::parse_str(RAW_SCHEMA).unwrap();
+    let encoded = encode(employee(), schema).unwrap();
+
+    let mut group = c.benchmark_group("compression");
+    group.throughput(Throughput::Bytes(encoded.len() as u64));
+
+    group.bench_function("gzip", |b| {
+        b.iter(|| compress(black_box(&encoded), Compression::Gzip).unwrap())
+    });
+
+    group.finish();
+}
+
+fn update_diff(c: &mut Criterion) {
+    let before = employee();
+    let update: UpdateDescription = from_document(doc! {
+        "updatedFields": {
+            "age": 33,
+            "project": { "title": "Awesome Project", "rating": 95.0_f64 },
+        },
+        "removedFields": ["nickname"],
+    })
+    .unwrap();
+
+    let mut group = c.benchmark_group("update_diff");
+    group.throughput(Throughput::Elements(1));
+
+    group.bench_function("changes", |b| {
+        b.iter(|| diff::changes(black_box(Some(&before)), black_box(&update)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, avro_encoding, compression, update_diff);
+criterion_main!(benches);
@@ -1,63 +1,343 @@
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, bail};
-use log::{debug, error, info};
-use mongodb::bson::{doc, Document};
+use apache_avro::Schema;
+use chrono::Utc;
+use cron::Schedule;
+use log::{debug, error, info, warn};
+use mongodb::bson::{doc, to_document, Bson, DateTime, Document};
 use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
 use mongodb::change_stream::ChangeStream;
-use mongodb::options::{ChangeStreamOptions, FullDocumentBeforeChangeType, FullDocumentType};
-use mongodb::Database;
-use tokio::sync::mpsc::Sender;
+use mongodb::options::{
+    ChangeStreamOptions, FindOneOptions, FindOptions, FullDocumentBeforeChangeType,
+    FullDocumentType,
+};
+use mongodb::{Cursor, Database};
+use tokio::task::JoinSet;
+use tokio::time::sleep;
 
-use crate::config::{Config, Connector, SchemaProviderName};
+use crate::config::{
+    Compression, Config, Connector, EncodeErrorPolicy, EventTimeCfg, FallbackCfg, PollFallbackCfg,
+    PollingCfg, ScheduleCfg, SchemaProviderName, UpdateDiff, PUBLISH_RETRIES,
+    PUBLISH_RETRY_BACKOFF_MS,
+};
+use crate::daemon::{panic_message, RestartBackoff, RESTART_LOOP};
 use crate::db::db_client;
-use crate::encoding::avro::encode;
+use crate::delivery::{DeliveryEntry, DeliveryLog};
+use crate::diff;
+use crate::encoding::avro::{encode, unknown_fields};
+use crate::encoding::compression::{compress, content_encoding};
+use crate::event_time;
+use crate::lineage::{LineageDataset, LineageEmitter};
+use crate::notification::{ErrorRateSla, JobEvent, Notifier, SchemaDrift};
 use crate::pubsub::{
+    api::Encoding,
     srvc::{PubSubPublisher, SchemaService},
-    GCPTokenProvider, ServiceAccountAuth,
+    GCPTokenProvider, ServiceAccountAuth, ENDPOINT,
 };
-use crate::schema::{MongoDbSchemaProvider, SchemaProvider};
+use crate::schema::{MongoDbSchemaProvider, SchemaProvider, SCHEMA_REGISTRY_COLLECTION};
 use crate::sink::EventSink;
 
-/// Listen to mongodb change streams and publish the events to a pubsub topic
-pub async fn listen_streams<TP>(done_ch: Sender<String>, cfg: Config, tp: TP) -> anyhow::Result<()>
+/// Listen to mongodb change streams and publish the events to a pubsub topic.
+/// Returns the connector tasks, a task resolves with an error once its connector fails.
+/// With `restart_failed` a failed connector is restarted with a backoff instead
+/// and resumes after the last event it consumed
+pub async fn listen_streams<TP>(
+    cfg: Config,
+    tp: TP,
+    restart_failed: bool,
+) -> anyhow::Result<JoinSet<anyhow::Result<()>>>
 where
     TP: GCPTokenProvider + Clone + 'static + Send + Sync,
 {
-    for connector_cfg in cfg.connectors {
+    let mut streams = JoinSet::new();
+
+    for connector_cfg in cfg.connectors.iter().cloned() {
         info!(
             "listening to: {}:{}",
-            connector_cfg.db_name, connector_cfg.db_collection
+            connector_cfg.db_name,
+            collections_label(&connector_cfg)
         );
 
         // token_provider is Arc and can be cloned without performance penalty
         let gcp_auth_inteceptor = ServiceAccountAuth(tp.clone());
-        let done_ch = done_ch.clone();
+        let notifier = Notifier::new(cfg.notifications_for(&connector_cfg))?;
+        let lineage = cfg
+            .lineage
+            .as_ref()
+            .map(|lineage_cfg| lineage_emitter(lineage_cfg.url.clone(), &connector_cfg))
+            .transpose()?;
 
-        tokio::spawn(async move {
+        streams.spawn(async move {
             let cnt_name = connector_cfg.name.clone();
-            let stream_listener = StreamListener::new(connector_cfg, gcp_auth_inteceptor).await;
+            let checkpoint = Checkpoint::default();
+            let mut backoff = RestartBackoff::new();
 
-            match stream_listener {
-                Ok(mut stream_listener) => {
-                    if let Err(err) = stream_listener.listen().await {
-                        error!("{err}")
-                    }
+            loop {
+                let started_at = Instant::now();
+                let result = run_connector(
+                    connector_cfg.clone(),
+                    gcp_auth_inteceptor.clone(),
+                    notifier.clone(),
+                    lineage.clone(),
+                    checkpoint.clone(),
+                )
+                .await;
+
+                let Err(err) = result else {
+                    return Ok(());
+                };
+
+                // delivered inline, the app may exit once the last connector stopped
+                notifier
+                    .deliver(JobEvent::Failed {
+                        connector: cnt_name.clone(),
+                        error: err.to_string(),
+                    })
+                    .await;
+
+                if !restart_failed {
+                    return Err(err.context(format!("connector: {}", cnt_name)));
                 }
-                Err(err) => error!("{err}"),
-            }
 
-            // send done signal
-            if let Err(err) = done_ch.send(cnt_name.clone()).await {
-                error!(
-                    "failed to send done signal: {}: connector: {}",
-                    err, cnt_name
-                );
+                let delay = backoff.next(started_at.elapsed());
+                error!("{err}. restarting connector {} in {:?}", cnt_name, delay);
+                if backoff.streak() == RESTART_LOOP {
+                    notifier.notify(JobEvent::RestartLoop {
+                        connector: cnt_name.clone(),
+                        restarts: backoff.streak(),
+                    });
+                }
+                sleep(delay).await;
             }
         });
     }
 
-    Ok(())
+    Ok(streams)
+}
+
+/// Runs the connector once, panics of the listener are returned as errors
+async fn run_connector<TP>(
+    connector: Connector,
+    auth_interceptor: ServiceAccountAuth<TP>,
+    notifier: Notifier,
+    lineage: Option<LineageEmitter>,
+    checkpoint: Checkpoint,
+) -> anyhow::Result<()>
+where
+    TP: GCPTokenProvider + Clone + 'static + Send + Sync,
+{
+    let mut listener = StreamListener::new(connector, auth_interceptor, notifier, lineage).await?;
+    listener.resume_from(checkpoint);
+
+    // the set aborts the listener once the connector task is aborted
+    let mut task = JoinSet::new();
+    task.spawn(async move { listener.listen().await });
+
+    match task.join_next().await {
+        Some(Ok(result)) => result,
+        Some(Err(err)) if err.is_panic() => Err(anyhow!(
+            "connector panicked: {}",
+            panic_message(err.into_panic())
+        )),
+        Some(Err(err)) => Err(anyhow!("connector task failed: {}", err)),
+        None => Ok(()),
+    }
+}
+
+/// Checkpoint shares the resume token of the last consumed event with the connector task,
+/// a restarted connector resumes from it
+#[derive(Clone, Default)]
+struct Checkpoint(Arc<Mutex<Option<ResumeToken>>>);
+
+impl Checkpoint {
+    fn save(&self, token: Option<ResumeToken>) {
+        *self.0.lock().unwrap() = token;
+    }
+
+    fn load(&self) -> Option<ResumeToken> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+fn lineage_emitter(url: String, connector: &Connector) -> anyhow::Result<LineageEmitter> {
+    LineageEmitter::new(
+        url,
+        connector.name.clone(),
+        lineage_datasets(connector),
+        connector.fields.clone(),
+        transformations(connector),
+    )
+}
+
+/// Pairs each watched collection with its rendered topic. The collections of a database watch
+/// are unknown upfront, a `{collection}` placeholder is kept in the topic name then
+fn lineage_datasets(connector: &Connector) -> Vec<LineageDataset> {
+    let dataset = |collection: &str| {
+        let mut namespace = HashMap::from([("database".to_owned(), connector.db_name.clone())]);
+        if collection != "*" {
+            namespace.insert("collection".to_owned(), collection.to_owned());
+        }
+
+        LineageDataset {
+            source: format!("{}.{}", connector.db_name, collection),
+            topic: render(&connector.topic, &namespace),
+        }
+    };
+
+    match connector.collections() {
+        Some(collections) => collections.iter().map(|coll| dataset(coll)).collect(),
+        None => vec![dataset("*")],
+    }
+}
+
+/// Lists the transformations applied to the documents in the order they are applied
+fn transformations(connector: &Connector) -> Vec<String> {
+    let mut transformations = Vec::new();
+    if !connector.pipeline.is_empty() {
+        let stages: Vec<&str> = connector
+            .pipeline
+            .iter()
+            .filter_map(|stage| stage.keys().next().map(String::as_str))
+            .collect();
+        transformations.push(format!("pipeline:{}", stages.join(",")));
+    }
+    if let Some(fields) = &connector.fields {
+        transformations.push(format!("projection:{}", fields.join(",")));
+    }
+    transformations.push("avro_encoding".to_owned());
+    if let Some(update_diff) = connector.update_diff {
+        transformations.push(format!("update_diff:{:?}", update_diff).to_lowercase());
+    }
+    if !connector.attributes.is_empty() {
+        let mut names: Vec<&str> = connector.attributes.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        transformations.push(format!("static_attributes:{}", names.join(",")));
+    }
+    if let Some(compression) = connector.compression {
+        transformations.push(format!("compression:{:?}", compression).to_lowercase());
+    }
+    transformations
+}
+
+fn collections_label(connector: &Connector) -> String {
+    match connector.collections() {
+        Some(collections) => collections.join(","),
+        None => "*".to_owned(),
+    }
+}
+
+/// Attributes mstream may set on a message, they can be listed in the required attributes
+/// along with the static attributes, but static attributes can't reuse their names
+const BUILTIN_ATTRIBUTES: [&str; 12] = [
+    "stream_name",
+    "operation_type",
+    "database",
+    "collection",
+    "event_time",
+    "event_id",
+    "schema_id",
+    "schema_version",
+    "scheduled_at",
+    "encoding",
+    "diff",
+    "diff_truncated",
+];
+
+/// Namespace placeholders that can be used in the topic name and the static attributes
+const NAMESPACE_PLACEHOLDERS: [&str; 2] = ["database", "collection"];
+
+fn is_template(s: &str) -> bool {
+    NAMESPACE_PLACEHOLDERS
+        .iter()
+        .any(|name| s.contains(&format!("{{{}}}", name)))
+}
+
+/// Replaces the namespace placeholders with the event attributes
+fn render(template: &str, attributes: &HashMap<String, String>) -> String {
+    NAMESPACE_PLACEHOLDERS
+        .iter()
+        .fold(template.to_owned(), |rendered, name| {
+            match attributes.get(*name) {
+                Some(value) => rendered.replace(&format!("{{{}}}", name), value),
+                None => rendered,
+            }
+        })
+}
+
+/// Change event fields the listener relies on, kept when the documents are projected
+const EVENT_FIELDS: [&str; 6] = [
+    "_id",
+    "operationType",
+    "ns",
+    "documentKey",
+    "clusterTime",
+    "updateDescription",
+];
+
+/// Builds a change stream projection of the selected fields of the full document and the pre-image.
+/// The event time field is always included
+fn projection(fields: &[String], event_time: Option<&EventTimeCfg>) -> Document {
+    let mut projection = Document::new();
+    for field in EVENT_FIELDS {
+        projection.insert(field, 1);
+    }
+
+    let event_time_field = event_time.map(|cfg| &cfg.field);
+    for field in fields.iter().chain(event_time_field) {
+        projection.insert(format!("fullDocument.{}", field), 1);
+        projection.insert(format!("fullDocumentBeforeChange.{}", field), 1);
+    }
+
+    projection
+}
+
+/// Builds a find projection of the selected fields, the watermark and the event time fields
+fn poll_projection(
+    fields: &[String],
+    watermark_field: &str,
+    event_time: Option<&EventTimeCfg>,
+) -> Document {
+    let mut projection = doc! { "_id": 1, watermark_field: 1 };
+    let event_time_field = event_time.map(|cfg| &cfg.field);
+    for field in fields.iter().chain(event_time_field) {
+        projection.insert(field, 1);
+    }
+
+    projection
+}
+
+/// Watermark is the position of the last polled document.
+/// `_id` breaks the ties between documents with the same watermark field value
+struct Watermark {
+    value: Bson,
+    id: Bson,
+}
+
+impl Watermark {
+    fn of(mongo_doc: &Document, field: &str) -> Self {
+        Self {
+            value: mongo_doc.get(field).cloned().unwrap_or(Bson::Null),
+            id: mongo_doc.get("_id").cloned().unwrap_or(Bson::Null),
+        }
+    }
+
+    /// Matches the documents after the watermark
+    fn filter(&self, field: &str) -> Document {
+        if field == "_id" {
+            return doc! { "_id": { "$gt": self.id.clone() } };
+        }
+
+        doc! {
+            "$or": [
+                { field: { "$gt": self.value.clone() } },
+                { field: self.value.clone(), "_id": { "$gt": self.id.clone() } },
+            ]
+        }
+    }
 }
 
 /// ChangeStream is a mongodb change stream
@@ -65,71 +345,369 @@ type CStream = ChangeStream<ChangeStreamEvent<Document>>;
 type Publisher = Box<dyn EventSink + Send + Sync>;
 type SchemaRegistry = Box<dyn SchemaProvider + Send + Sync>;
 
+/// EventPayload is the content published for a change stream event
+enum EventPayload {
+    /// Document to be encoded with the connector schema
+    Document(Document),
+    /// Already encoded json payload
+    Json(Vec<u8>),
+}
+
+/// Published identifies the topic a message was published to
+struct Published {
+    topic: String,
+    message_id: String,
+}
+
+/// FatalError stops the connector instead of failing a single event
+#[derive(Debug)]
+struct FatalError(anyhow::Error);
+
+impl std::fmt::Display for FatalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for FatalError {}
+
+/// Max length of the `error` attribute of deadlettered messages, pubsub limits attribute values to 1024 bytes
+const MAX_ERROR_ATTRIBUTE_LEN: usize = 1000;
+
+/// Max length of the `diff` attribute, pubsub limits attribute values to 1024 bytes.
+/// Larger diffs are dropped and flagged with `diff_truncated`
+const MAX_DIFF_ATTRIBUTE_LEN: usize = 1024;
+
+fn is_power_of_ten(mut n: u64) -> bool {
+    while n >= 10 && n.is_multiple_of(10) {
+        n /= 10;
+    }
+    n == 1
+}
+
+/// Truncates the value to at most `max` bytes at a char boundary
+fn truncate_attribute(value: &mut String, max: usize) -> bool {
+    if value.len() <= max {
+        return false;
+    }
+
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    true
+}
+
 /// StreamListener listens to a mongodb change stream and publishes the events to a pubsub topic
 struct StreamListener {
     connector_name: String,
     schema_name: String,
     topic: String,
+    static_attributes: HashMap<String, String>,
+    required_attributes: Vec<String>,
     db: Database,
     db_name: String,
-    db_collection: String,
+    /// Watched collections, `None` for the whole database
+    collections: Option<Vec<String>>,
+    /// Collections mstream writes to itself, excluded from a database watch
+    internal_collections: Vec<String>,
+    fields: Option<Vec<String>>,
     schema_srvc: SchemaRegistry,
+    /// Version of the connector schema, resolved once the schema is fetched at warmup
+    schema_version: String,
     publisher: Publisher,
     resume_token: Option<ResumeToken>,
+    /// Canonical form of the schema attached to the topic, if validated
+    topic_schema: Option<String>,
+    update_diff: Option<UpdateDiff>,
+    pipeline: Vec<Document>,
+    compression: Option<Compression>,
+    on_encode_error: EncodeErrorPolicy,
+    skipped_events: u64,
+    delivery_log: Option<DeliveryLog>,
+    lineage: Option<LineageEmitter>,
+    polling: PollingCfg,
+    event_time: Option<EventTimeCfg>,
+    fallback: Option<FallbackCfg>,
+    poll_fallback: Option<PollFallbackCfg>,
+    schedule: Option<(Schedule, ScheduleCfg)>,
+    late_events: u64,
+    notifier: Notifier,
+    lagging: bool,
+    error_rate: Option<ErrorRateSla>,
+    schema_drift: SchemaDrift,
+    checkpoint: Checkpoint,
 }
 
 impl StreamListener {
     async fn new<P>(
         connector: Connector,
         auth_interceptor: ServiceAccountAuth<P>,
+        notifier: Notifier,
+        lineage: Option<LineageEmitter>,
     ) -> anyhow::Result<StreamListener>
     where
         P: GCPTokenProvider + Clone + 'static + Send + Sync,
     {
-        let publisher = get_publisher_service(auth_interceptor.clone()).await?;
-        let db = db_client(connector.name.clone(), &connector.db_connection)
-            .await?
-            .database(&connector.db_name);
+        let endpoint = connector
+            .pubsub_endpoint
+            .clone()
+            .unwrap_or_else(|| ENDPOINT.to_owned());
 
-        let schema_srvc =
-            get_schema_service(connector.schema.provider, auth_interceptor, db.clone()).await?;
+        let publisher = get_publisher_service(&endpoint, auth_interceptor.clone()).await?;
+        let db = db_client(
+            connector.name.clone(),
+            &connector.db_connection,
+            &connector.mongodb,
+        )
+        .await?
+        .database(&connector.db_name);
+        let collections = connector.collections();
+
+        if connector.validate_topic_schema && connector.compression.is_some() {
+            bail!(
+                "validate_topic_schema can't be combined with compression, topics with a schema reject compressed payloads. connector: {}",
+                &connector.name
+            );
+        }
+
+        if connector.validate_topic_schema && connector.update_diff == Some(UpdateDiff::Payload) {
+            bail!(
+                "validate_topic_schema can't be combined with update_diff = payload, json diffs bypass the topic schema. connector: {}",
+                &connector.name
+            );
+        }
+
+        let topic_schema = match connector.validate_topic_schema {
+            true if is_template(&connector.topic) => {
+                bail!(
+                    "validate_topic_schema is not supported with a templated topic: {}",
+                    &connector.topic
+                );
+            }
+            true => {
+                let topic = connector.topic.clone();
+                let topic_schema =
+                    get_topic_schema(&endpoint, auth_interceptor.clone(), topic).await?;
+                Some(topic_schema.canonical_form())
+            }
+            false => None,
+        };
+
+        let mut reserved: Vec<&str> = connector
+            .attributes
+            .keys()
+            .map(String::as_str)
+            .filter(|name| BUILTIN_ATTRIBUTES.contains(name))
+            .collect();
+        if !reserved.is_empty() {
+            reserved.sort_unstable();
+            bail!(
+                "static attributes clash with built-in attributes: {}. connector: {}",
+                reserved.join(", "),
+                &connector.name
+            );
+        }
+
+        let unknown_required: Vec<&str> = connector
+            .required_attributes
+            .iter()
+            .filter(|name| {
+                !connector.attributes.contains_key(*name)
+                    && !BUILTIN_ATTRIBUTES.contains(&name.as_str())
+            })
+            .map(String::as_str)
+            .collect();
+        if !unknown_required.is_empty() {
+            bail!(
+                "required attributes are neither static nor built-in attributes: {}. connector: {}",
+                unknown_required.join(", "),
+                &connector.name
+            );
+        }
+
+        if connector.skip_delivered && connector.delivery_log.is_none() {
+            bail!(
+                "skip_delivered requires a delivery_log. connector: {}",
+                &connector.name
+            );
+        }
+
+        let schedule = match connector.schedule {
+            Some(_) if connector.poll_fallback.is_some() => bail!(
+                "schedule can't be combined with poll_fallback. connector: {}",
+                &connector.name
+            ),
+            Some(cfg) if cfg.filter.is_some() && cfg.aggregate.is_some() => bail!(
+                "schedule accepts either a filter or aggregate stages, not both. connector: {}",
+                &connector.name
+            ),
+            Some(cfg) => {
+                let schedule = Schedule::from_str(&cfg.cron).map_err(|err| {
+                    anyhow!(
+                        "invalid schedule cron '{}': {err}. connector: {}",
+                        &cfg.cron,
+                        &connector.name
+                    )
+                })?;
+                Some((schedule, cfg))
+            }
+            None => None,
+        };
+
+        let delivery_log = match &connector.delivery_log {
+            Some(collection) => Some(DeliveryLog::new(&db, collection).await?),
+            None => None,
+        };
+
+        let resume_token = match (&delivery_log, connector.skip_delivered) {
+            (Some(delivery_log), true) => delivery_log.last_cursor(&connector.name).await?,
+            _ => None,
+        };
+        if resume_token.is_some() {
+            info!(
+                "resuming after the last delivered event. connector: {}",
+                &connector.name
+            );
+        }
+
+        // the delivery log and the schema registry live in the connector database
+        let mut internal_collections = Vec::new();
+        if let Some(delivery_log) = &delivery_log {
+            internal_collections.push(delivery_log.collection_name().to_owned());
+        }
+        if let SchemaProviderName::MongoDB = connector.schema.provider {
+            internal_collections.push(SCHEMA_REGISTRY_COLLECTION.to_owned());
+        }
+
+        let schema_srvc = get_schema_service(
+            &endpoint,
+            connector.schema.provider,
+            auth_interceptor,
+            db.clone(),
+        )
+        .await?;
 
         Ok(StreamListener {
             connector_name: connector.name,
             schema_name: connector.schema.id,
             topic: connector.topic,
+            static_attributes: connector.attributes,
+            required_attributes: connector.required_attributes,
             db_name: connector.db_name,
-            db_collection: connector.db_collection,
+            collections,
+            internal_collections,
+            fields: connector.fields,
             publisher,
             db,
-            resume_token: None,
+            resume_token,
+            topic_schema,
+            update_diff: connector.update_diff,
+            pipeline: connector.pipeline,
+            compression: connector.compression,
+            on_encode_error: connector.on_encode_error,
+            skipped_events: 0,
+            delivery_log,
+            lineage,
+            polling: connector.polling,
+            event_time: connector.event_time,
+            fallback: connector.fallback,
+            poll_fallback: connector.poll_fallback,
+            schedule,
+            late_events: 0,
             schema_srvc,
+            schema_version: String::new(),
+            error_rate: notifier.error_rate_sla(),
+            notifier,
+            lagging: false,
+            schema_drift: SchemaDrift::new(),
+            checkpoint: Checkpoint::default(),
         })
     }
 
+    /// Shares the consumed events with the checkpoint, a saved resume token
+    /// takes precedence over the last delivered event of the delivery log
+    fn resume_from(&mut self, checkpoint: Checkpoint) {
+        if let Some(token) = checkpoint.load() {
+            info!(
+                "resuming after the last consumed event. connector: {}",
+                &self.connector_name
+            );
+            self.resume_token = Some(token);
+        }
+        self.checkpoint = checkpoint;
+    }
+
     /// Listen to a mongodb change stream and publish the events to a pubsub topic
     async fn listen(&mut self) -> anyhow::Result<()> {
+        self.warmup().await?;
+
+        if let Some(schedule) = self.schedule.clone() {
+            return self.run_schedule(schedule).await;
+        }
+
+        let supports_change_streams = self.supports_change_streams().await?;
+        match self.poll_fallback.clone() {
+            Some(cfg) if cfg.force || !supports_change_streams => return self.poll(cfg).await,
+            None if !supports_change_streams => bail!(
+                "change streams require a replica set or a sharded cluster, configure poll_fallback to poll a standalone server. stream: {}",
+                &self.connector_name
+            ),
+            _ => {}
+        }
+
         let mut cs = self.change_stream().await?;
+        let idle_backoff = self.polling.idle_backoff();
+        let mut idle_pause = idle_backoff.map(|(min, _)| min);
 
         while cs.is_alive() {
             let Some(event) = cs.next_if_any().await? else {
+                // back off while the stream is idle
+                if let (Some(pause), Some((_, max))) = (idle_pause, idle_backoff) {
+                    sleep(pause).await;
+                    idle_pause = Some((pause * 2).min(max));
+                }
                 continue;
             };
-            let attributes = self.event_metadata(&event);
-            // self.resume_token = cs.resume_token();
+            idle_pause = idle_backoff.map(|(min, _)| min);
+
+            let mut attributes = self.event_metadata(&event);
+            self.check_lag(&event);
+            let event_doc = event
+                .full_document
+                .as_ref()
+                .or(event.full_document_before_change.as_ref());
+            self.stamp_event_time(event_doc, &mut attributes);
+            let consumed_token = std::mem::replace(&mut self.resume_token, cs.resume_token());
+            self.checkpoint.save(self.resume_token.clone());
 
-            let mongo_doc = match event.operation_type {
-                OperationType::Insert | OperationType::Update => {
-                    debug!("got insert/update event: {:?}", event);
-                    event.full_document
+            let payload = match event.operation_type {
+                OperationType::Insert => {
+                    debug!("got insert event: {:?}", event);
+                    event.full_document.clone().map(EventPayload::Document)
+                }
+                OperationType::Update => {
+                    debug!("got update event: {:?}", event);
+                    self.update_payload(&event, &mut attributes)
                 }
                 OperationType::Delete => {
                     debug!("got delete event: {:?}", event);
-                    event.full_document_before_change
+                    event
+                        .full_document_before_change
+                        .clone()
+                        .map(EventPayload::Document)
                 }
                 OperationType::Invalidate => {
                     bail!("got invalidate event: {:?}", event);
                 }
+                OperationType::Drop if self.single_collection().is_none() => {
+                    warn!(
+                        "collection dropped: {:?}. stream: {}",
+                        event.ns, &self.connector_name
+                    );
+                    None
+                }
                 OperationType::Drop => {
                     bail!("got drop event: {:?}", event);
                 }
@@ -141,89 +719,818 @@ impl StreamListener {
                 _ => None,
             };
 
-            if let Some(mongo_doc) = mongo_doc {
-                _ = &self
-                    .process_event(mongo_doc, attributes)
-                    .await
-                    .map_err(|err| error!("{err}"));
+            if let Some(payload) = payload {
+                let result = self.process_event(payload, attributes).await;
+                self.check_error_rate(result.is_err());
+
+                match result {
+                    Ok(published) => self.record_delivery(&event, published).await,
+                    Err(err) if err.is::<FatalError>() => {
+                        // the event is consumed again once the connector is restarted
+                        self.checkpoint.save(consumed_token);
+                        return Err(err);
+                    }
+                    Err(err) => error!("{err}"),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the schema and checks the database connection before consuming the change stream,
+    /// so that the first event doesn't pay for it and misconfiguration fails the connector early
+    async fn warmup(&mut self) -> anyhow::Result<()> {
+        let started_at = Instant::now();
+
+        let (schema, ping) = tokio::join!(
+            self.schema_srvc.get_schema(self.schema_name.clone()),
+            self.db.run_command(doc! { "ping": 1 }, None),
+        );
+
+        let schema = schema.map_err(|err| {
+            anyhow!(
+                "failed to fetch schema {}: {}. stream: {}",
+                &self.schema_name,
+                err,
+                &self.connector_name
+            )
+        })?;
+        ping.map_err(|err| {
+            anyhow!(
+                "failed to ping database {}: {}. stream: {}",
+                &self.db_name,
+                err,
+                &self.connector_name
+            )
+        })?;
+
+        self.check_topic_schema(&schema)?;
+        self.schema_version = self.schema_srvc.schema_version(&self.schema_name, &schema);
+
+        if let Some(lineage) = &self.lineage {
+            lineage.emit_start(&schema);
+        }
+
+        info!(
+            "warmup took {:?}. stream: {}",
+            started_at.elapsed(),
+            &self.connector_name
+        );
+
+        Ok(())
+    }
+
+    fn single_collection(&self) -> Option<&str> {
+        match self.collections.as_deref() {
+            Some([coll]) => Some(coll),
+            _ => None,
+        }
+    }
+
+    /// Change streams are available on replica sets and sharded clusters only
+    async fn supports_change_streams(&self) -> anyhow::Result<bool> {
+        // isMaster is still supported by the mongodb compatible services, unlike hello
+        let reply = self.db.run_command(doc! { "isMaster": 1 }, None).await?;
+        Ok(reply.contains_key("setName") || reply.get_str("msg") == Ok("isdbgrid"))
+    }
+
+    /// Polls the collection for documents past the watermark, starting after the latest document.
+    /// Only new and, with an update timestamp as the watermark, updated documents are seen
+    async fn poll(&mut self, cfg: PollFallbackCfg) -> anyhow::Result<()> {
+        let Some(coll_name) = self.single_collection().map(str::to_owned) else {
+            bail!(
+                "poll_fallback requires a single db_collection. stream: {}",
+                &self.connector_name
+            );
+        };
+
+        let coll = self.db.collection::<Document>(&coll_name);
+        let field = cfg.watermark_field.as_str();
+        let (sort, reverse) = match field {
+            "_id" => (doc! { "_id": 1 }, doc! { "_id": -1 }),
+            _ => (doc! { field: 1, "_id": 1 }, doc! { field: -1, "_id": -1 }),
+        };
+        let projection = self
+            .fields
+            .as_deref()
+            .map(|fields| poll_projection(fields, field, self.event_time.as_ref()));
+
+        let latest = coll
+            .find_one(None, FindOneOptions::builder().sort(reverse).build())
+            .await?;
+        let mut watermark = latest.map(|mongo_doc| Watermark::of(&mongo_doc, field));
+        let operation_type = match field {
+            "_id" => "insert",
+            _ => "upsert",
+        };
+
+        info!(
+            "polling {}:{} by '{}'. stream: {}",
+            &self.db_name, &coll_name, field, &self.connector_name
+        );
+
+        loop {
+            let filter = watermark.as_ref().map(|w| w.filter(field));
+            let opts = FindOptions::builder()
+                .sort(sort.clone())
+                .limit(cfg.batch_size)
+                .projection(projection.clone())
+                .build();
+
+            let mut cursor = coll.find(filter, opts).await?;
+            let mut polled = 0;
+
+            while cursor.advance().await? {
+                let mongo_doc = cursor.deserialize_current()?;
+                watermark = Some(Watermark::of(&mongo_doc, field));
+                polled += 1;
+
+                let mut attributes = HashMap::from([
+                    ("stream_name".to_owned(), self.connector_name.clone()),
+                    ("operation_type".to_owned(), operation_type.to_owned()),
+                    ("database".to_owned(), self.db_name.clone()),
+                    ("collection".to_owned(), coll_name.clone()),
+                ]);
+                self.stamp_event_time(Some(&mongo_doc), &mut attributes);
+
+                let result = self
+                    .process_event(EventPayload::Document(mongo_doc), attributes)
+                    .await;
+                self.check_error_rate(result.is_err());
+
+                match result {
+                    Err(err) if err.is::<FatalError>() => return Err(err),
+                    Err(err) => error!("{err}"),
+                    Ok(_) => {}
+                }
+            }
+
+            if polled == 0 {
+                sleep(cfg.interval()).await;
             }
         }
+    }
+
+    /// Runs the scheduled query on every tick and publishes the result documents
+    async fn run_schedule(
+        &mut self,
+        (schedule, cfg): (Schedule, ScheduleCfg),
+    ) -> anyhow::Result<()> {
+        let Some(coll_name) = self.single_collection().map(str::to_owned) else {
+            bail!(
+                "schedule requires a single db_collection. stream: {}",
+                &self.connector_name
+            );
+        };
+
+        let coll = self.db.collection::<Document>(&coll_name);
+        let projection = self
+            .fields
+            .as_deref()
+            .map(|fields| poll_projection(fields, "_id", self.event_time.as_ref()));
+
+        info!(
+            "scheduled query on {}:{} at '{}'. stream: {}",
+            &self.db_name, &coll_name, &cfg.cron, &self.connector_name
+        );
+
+        // ticks missed while a run was still publishing are skipped
+        while let Some(tick) = schedule.upcoming(Utc).next() {
+            sleep((tick - Utc::now()).to_std().unwrap_or_default()).await;
+
+            let scheduled_at = tick.to_rfc3339();
+            let cursor = match &cfg.aggregate {
+                Some(stages) => coll.aggregate(stages.clone(), None).await,
+                None => {
+                    let opts = FindOptions::builder()
+                        .projection(projection.clone())
+                        .build();
+                    coll.find(cfg.filter.clone(), opts).await
+                }
+            };
+
+            // a failed run is retried on the next tick, published documents are published again then
+            let result = match cursor {
+                Ok(mut cursor) => {
+                    self.publish_query(&mut cursor, &coll_name, &scheduled_at)
+                        .await
+                }
+                Err(err) => Err(err.into()),
+            };
+
+            match result {
+                Ok(queried) => info!(
+                    "scheduled query at {} read {} documents. stream: {}",
+                    &scheduled_at, queried, &self.connector_name
+                ),
+                Err(err) if err.is::<FatalError>() => return Err(err),
+                Err(err) => error!(
+                    "scheduled query at {} failed, waiting for the next run: {:#}. stream: {}",
+                    &scheduled_at, err, &self.connector_name
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Publishes the documents of a scheduled query, returns the number of documents read
+    async fn publish_query(
+        &mut self,
+        cursor: &mut Cursor<Document>,
+        coll_name: &str,
+        scheduled_at: &str,
+    ) -> anyhow::Result<usize> {
+        let mut queried = 0;
+
+        while cursor.advance().await? {
+            let mongo_doc = cursor.deserialize_current()?;
+            queried += 1;
+
+            let mut attributes = HashMap::from([
+                ("stream_name".to_owned(), self.connector_name.clone()),
+                ("operation_type".to_owned(), "query".to_owned()),
+                ("database".to_owned(), self.db_name.clone()),
+                ("collection".to_owned(), coll_name.to_owned()),
+                ("scheduled_at".to_owned(), scheduled_at.to_owned()),
+            ]);
+            self.stamp_event_time(Some(&mongo_doc), &mut attributes);
+
+            let result = self
+                .process_event(EventPayload::Document(mongo_doc), attributes)
+                .await;
+            self.check_error_rate(result.is_err());
+
+            match result {
+                Err(err) if err.is::<FatalError>() => return Err(err),
+                Err(err) => error!("{err}"),
+                Ok(_) => {}
+            }
+        }
+
+        Ok(queried)
+    }
+
+    /// Builds the payload of an update event according to the update diff settings
+    fn update_payload(
+        &self,
+        event: &ChangeStreamEvent<Document>,
+        attributes: &mut HashMap<String, String>,
+    ) -> Option<EventPayload> {
+        let Some(mode) = self.update_diff else {
+            return event.full_document.clone().map(EventPayload::Document);
+        };
+
+        let Some(update) = &event.update_description else {
+            warn!(
+                "update description is not available, publishing update without diff. stream: {}",
+                &self.connector_name
+            );
+            return event.full_document.clone().map(EventPayload::Document);
+        };
+
+        if event.full_document_before_change.is_none() {
+            debug!(
+                "document pre-image is not available, diff has no before values. stream: {}",
+                &self.connector_name
+            );
+        }
+
+        // the diff is taken from the update itself, the looked up document may include later writes
+        let changes = diff::changes(event.full_document_before_change.as_ref(), update);
+
+        match mode {
+            UpdateDiff::Attribute => {
+                // a cut json diff can't be parsed, so the diff is dropped as a whole
+                let diff = changes.to_string();
+                if diff.len() > MAX_DIFF_ATTRIBUTE_LEN {
+                    debug!(
+                        "diff of {} bytes exceeds the attribute limit and is dropped. stream: {}",
+                        diff.len(),
+                        &self.connector_name
+                    );
+                    attributes.insert("diff_truncated".to_owned(), "true".to_owned());
+                } else {
+                    attributes.insert("diff".to_owned(), diff);
+                }
+                event.full_document.clone().map(EventPayload::Document)
+            }
+            UpdateDiff::Payload => {
+                attributes.insert("encoding".to_owned(), "json".to_owned());
+                Some(EventPayload::Json(changes.to_string().into_bytes()))
+            }
+        }
+    }
+
+    /// Stores the published message id together with the change stream event cursor
+    async fn record_delivery(&self, event: &ChangeStreamEvent<Document>, published: Published) {
+        let Some(delivery_log) = &self.delivery_log else {
+            return;
+        };
+
+        let entry = DeliveryEntry {
+            connector: self.connector_name.clone(),
+            cursor: event.id.clone(),
+            operation_type: format!("{:?}", event.operation_type).to_lowercase(),
+            document_key: event.document_key.clone(),
+            topic: published.topic,
+            message_id: published.message_id,
+            published_at: DateTime::now(),
+        };
+
+        if let Err(err) = delivery_log.record(entry).await {
+            error!(
+                "failed to record delivery: {}. stream: {}",
+                err, &self.connector_name
+            );
+        }
+    }
+
+    /// Adds the `event_time` attribute read from the configured document field
+    fn stamp_event_time(
+        &mut self,
+        mongo_doc: Option<&Document>,
+        attributes: &mut HashMap<String, String>,
+    ) {
+        let Some(cfg) = &self.event_time else {
+            return;
+        };
+
+        let extracted = mongo_doc.and_then(|doc| event_time::extract(doc, &cfg.field));
+
+        let Some(mut event_time) = extracted else {
+            debug!(
+                "event time field '{}' not found. stream: {}",
+                cfg.field, &self.connector_name
+            );
+            return;
+        };
+
+        let now = DateTime::now().timestamp_millis();
+        let skew_millis = cfg.max_clock_skew_secs as i64 * 1000;
+
+        if event_time.timestamp_millis() > now + skew_millis {
+            warn!(
+                "event time {} is ahead of the processing time beyond the allowed clock skew, using processing time. stream: {}",
+                event_time, &self.connector_name
+            );
+            event_time = DateTime::from_millis(now);
+        }
+
+        if let Some(late_after_secs) = cfg.late_after_secs {
+            let delay_millis = now - event_time.timestamp_millis();
+            if delay_millis > late_after_secs as i64 * 1000 {
+                self.late_events += 1;
+                warn!(
+                    "late event arrived {}ms after its event time, late events: {}. stream: {}",
+                    delay_millis, self.late_events, &self.connector_name
+                );
+            }
+        }
+
+        match event_time.try_to_rfc3339_string() {
+            Ok(formatted) => {
+                attributes.insert("event_time".to_owned(), formatted);
+            }
+            Err(err) => warn!(
+                "failed to format event time: {}. stream: {}",
+                err, &self.connector_name
+            ),
+        }
+    }
+
+    /// Notifies when the lag between the event cluster time and now crosses the configured threshold
+    fn check_lag(&mut self, event: &ChangeStreamEvent<Document>) {
+        let (Some(threshold), Some(cluster_time)) =
+            (self.notifier.lag_threshold_secs(), event.cluster_time)
+        else {
+            return;
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let lag_secs = now.saturating_sub(cluster_time.time as u64);
+
+        if lag_secs > threshold && !self.lagging {
+            self.lagging = true;
+            self.notifier.notify(JobEvent::Lagging {
+                connector: self.connector_name.clone(),
+                lag_secs,
+            });
+        } else if lag_secs <= threshold && self.lagging {
+            self.lagging = false;
+            self.notifier.notify(JobEvent::Recovered {
+                connector: self.connector_name.clone(),
+                lag_secs,
+            });
+        }
+    }
+
+    /// Stops the connector if its schema differs from the schema attached to the topic.
+    /// The payloads are decoded with the topic schema, so the canonical forms need to match
+    fn check_topic_schema(&self, schema: &Schema) -> anyhow::Result<()> {
+        let Some(topic_schema) = &self.topic_schema else {
+            return Ok(());
+        };
+
+        if schema.canonical_form() != *topic_schema {
+            bail!(
+                "connector schema {} drifted from the schema of topic {}. stream: {}",
+                &self.schema_name,
+                &self.topic,
+                &self.connector_name
+            );
+        }
 
         Ok(())
     }
 
+    /// Reports document fields the schema doesn't know about, once per field.
+    /// Notifications are batched, see [SchemaDrift]
+    fn check_unknown_fields(&mut self, mongo_doc: &Document, schema: &Schema) {
+        let fields = self.schema_drift.record(unknown_fields(mongo_doc, schema));
+
+        if !fields.is_empty() {
+            warn!(
+                "fields unknown to schema {} are dropped: {:?}. stream: {}",
+                &self.schema_name, &fields, &self.connector_name
+            );
+        }
+
+        if let Some(event) = self.schema_drift.event(&self.connector_name) {
+            self.notifier.notify(event);
+        }
+    }
+
+    fn check_error_rate(&mut self, failed: bool) {
+        let Some(sla) = self.error_rate.as_mut() else {
+            return;
+        };
+
+        if let Some(event) = sla.record(&self.connector_name, failed) {
+            self.notifier.notify(event);
+        }
+    }
+
     fn event_metadata(&self, event: &ChangeStreamEvent<Document>) -> HashMap<String, String> {
+        let mut attributes = self.namespace_metadata(event);
+        // the resume token is unique per event and stable across redeliveries
+        if let Some(event_id) = to_document(&event.id)
+            .ok()
+            .and_then(|token| token.get_str("_data").ok().map(str::to_owned))
+        {
+            attributes.insert("event_id".to_owned(), event_id);
+        }
+
+        attributes
+    }
+
+    fn namespace_metadata(&self, event: &ChangeStreamEvent<Document>) -> HashMap<String, String> {
+        let ns = event.ns.as_ref();
+        let database = ns.map(|ns| ns.db.clone()).unwrap_or(self.db_name.clone());
+        let collection = ns
+            .and_then(|ns| ns.coll.clone())
+            .or(self.single_collection().map(str::to_owned))
+            .unwrap_or_default();
+
         HashMap::from([
             ("stream_name".to_owned(), self.connector_name.clone()),
             (
                 "operation_type".to_owned(),
                 format!("{:?}", event.operation_type).to_lowercase(),
             ),
-            ("database".to_owned(), self.db_name.clone()),
-            ("collection".to_owned(), self.db_collection.clone()),
+            ("database".to_owned(), database),
+            ("collection".to_owned(), collection),
         ])
     }
 
     async fn process_event(
         &mut self,
-        mongo_doc: Document,
-        attributes: HashMap<String, String>,
-    ) -> anyhow::Result<()> {
+        payload: EventPayload,
+        mut attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        let mongo_doc = match payload {
+            EventPayload::Document(mongo_doc) => mongo_doc,
+            EventPayload::Json(b) => return self.publish(b, attributes).await,
+        };
+
         let schema = self
             .schema_srvc
             .get_schema(self.schema_name.clone())
             .await?;
-        let avro_encoded = encode(mongo_doc, schema)?;
 
-        let message = self
+        self.check_unknown_fields(&mongo_doc, &schema);
+
+        attributes.insert("schema_id".to_owned(), self.schema_name.clone());
+        attributes.insert("schema_version".to_owned(), self.schema_version.clone());
+
+        // the document is consumed by the encoding and only kept if it may be deadlettered
+        let deadletter_doc = match self.on_encode_error {
+            EncodeErrorPolicy::Deadletter { .. } => Some(mongo_doc.clone()),
+            _ => None,
+        };
+
+        match self.encode(mongo_doc, schema).await {
+            Ok(avro_encoded) => self.publish(avro_encoded, attributes).await,
+            Err(err) => self.encode_failed(err, deadletter_doc, attributes).await,
+        }
+    }
+
+    /// Encodes the document with the connector schema
+    async fn encode(&self, mongo_doc: Document, schema: Schema) -> anyhow::Result<Vec<u8>> {
+        // encoding is cpu bound and kept off the async worker threads
+        let avro_encoded = tokio::task::spawn_blocking(move || encode(mongo_doc, schema)).await??;
+        Ok(avro_encoded)
+    }
+
+    /// Applies the encode error policy
+    async fn encode_failed(
+        &mut self,
+        err: anyhow::Error,
+        mongo_doc: Option<Document>,
+        mut attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        let err = err.context(format!(
+            "failed to encode document. stream: {}",
+            &self.connector_name
+        ));
+
+        match self.on_encode_error.clone() {
+            EncodeErrorPolicy::Skip => {
+                self.skipped_events += 1;
+                error!(
+                    "skipped documents: {}. stream: {}",
+                    self.skipped_events, &self.connector_name
+                );
+
+                // notified on the 1st, 10th, 100th, ... skipped document
+                if is_power_of_ten(self.skipped_events) {
+                    self.notifier.notify(JobEvent::EventsSkipped {
+                        connector: self.connector_name.clone(),
+                        skipped: self.skipped_events,
+                    });
+                }
+                Err(err)
+            }
+            EncodeErrorPolicy::Fail => Err(FatalError(err).into()),
+            EncodeErrorPolicy::Deadletter { topic } => {
+                let payload = Bson::Document(mongo_doc.unwrap_or_default())
+                    .into_relaxed_extjson()
+                    .to_string()
+                    .into_bytes();
+
+                let mut error = format!("{:#}", err);
+                truncate_attribute(&mut error, MAX_ERROR_ATTRIBUTE_LEN);
+
+                attributes.insert("encoding".to_owned(), "json".to_owned());
+                attributes.insert("error".to_owned(), error);
+                attributes.insert("deadletter_from".to_owned(), self.topic.clone());
+
+                warn!("{:#}, sending to deadletter topic {}", err, &topic);
+                self.publish_deadletter(&topic, payload, attributes).await
+            }
+        }
+    }
+
+    /// Publishes to the connector topic, falling back to the configured topics in order
+    /// if it fails. Every event starts with the connector topic again
+    async fn publish(
+        &mut self,
+        payload: Vec<u8>,
+        attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        let (payload, attributes) = self.prepare_message(payload, attributes)?;
+
+        let fallback_topics = self
+            .fallback
+            .iter()
+            .flat_map(|fallback| fallback.topics.iter());
+        let topics = std::iter::once(&self.topic)
+            .chain(fallback_topics)
+            .map(|topic| render(topic, &attributes))
+            .collect();
+
+        self.publish_with_retries(topics, payload, attributes).await
+    }
+
+    /// Publishes a document that failed to encode to the deadletter topic,
+    /// with the attributes and retries of the connector topic but without fallbacks
+    async fn publish_deadletter(
+        &mut self,
+        topic: &str,
+        payload: Vec<u8>,
+        attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        let (payload, attributes) = self.prepare_message(payload, attributes)?;
+        let topic = render(topic, &attributes);
+
+        self.publish_with_retries(vec![topic], payload, attributes)
+            .await
+    }
+
+    /// Adds the static attributes, checks the required attributes and compresses the payload
+    fn prepare_message(
+        &self,
+        payload: Vec<u8>,
+        mut attributes: HashMap<String, String>,
+    ) -> anyhow::Result<(Vec<u8>, HashMap<String, String>)> {
+        for (key, value) in self.static_attributes.iter() {
+            if !attributes.contains_key(key) {
+                attributes.insert(key.clone(), render(value, &attributes));
+            }
+        }
+
+        let missing: Vec<&str> = self
+            .required_attributes
+            .iter()
+            .filter(|name| attributes.get(*name).is_none_or(String::is_empty))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "message is missing required attributes: {}. stream: {}",
+                missing.join(", "),
+                &self.connector_name
+            );
+        }
+
+        let payload = match self.compression {
+            Some(compression) => {
+                attributes.insert(
+                    "content_encoding".to_owned(),
+                    content_encoding(compression).to_owned(),
+                );
+                compress(&payload, compression)?
+            }
+            None => payload,
+        };
+
+        Ok((payload, attributes))
+    }
+
+    /// Tries the topics in order, each one is retried before moving on to the next.
+    /// Messages published to a later topic are flagged with `fallback_from`
+    async fn publish_with_retries(
+        &mut self,
+        topics: Vec<String>,
+        payload: Vec<u8>,
+        attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        // without a fallback the topics are retried with the default settings
+        let (retries, retry_backoff) = match &self.fallback {
+            Some(fallback) => (fallback.retries, fallback.retry_backoff()),
+            None => (
+                PUBLISH_RETRIES,
+                Duration::from_millis(PUBLISH_RETRY_BACKOFF_MS),
+            ),
+        };
+
+        let mut last_err = anyhow!("no topic to publish to");
+        for (i, topic) in topics.iter().enumerate() {
+            let mut attributes = attributes.clone();
+            if i > 0 {
+                attributes.insert("fallback_from".to_owned(), topics[0].clone());
+            }
+
+            for attempt in 0..=retries {
+                if attempt > 0 {
+                    sleep(retry_backoff).await;
+                }
+
+                match self
+                    .publish_to(topic.clone(), payload.clone(), attributes.clone())
+                    .await
+                {
+                    Ok(published) => return Ok(published),
+                    Err(err) => {
+                        warn!(
+                            "failed to publish, attempt {}: {}. stream: {}. topic: {}",
+                            attempt + 1,
+                            err,
+                            &self.connector_name,
+                            &topic
+                        );
+                        last_err = err;
+                    }
+                }
+            }
+        }
+
+        Err(last_err.context(format!(
+            "all topics failed. stream: {}",
+            &self.connector_name
+        )))
+    }
+
+    async fn publish_to(
+        &mut self,
+        topic: String,
+        payload: Vec<u8>,
+        attributes: HashMap<String, String>,
+    ) -> anyhow::Result<Published> {
+        let message_id = self
             .publisher
-            .publish(self.topic.clone(), avro_encoded, attributes)
+            .publish(topic.clone(), payload, attributes)
             .await?;
 
         info!(
             "successfully published a message: {:?}. stream: {}. schema: {}. topic: {}",
-            message, &self.connector_name, &self.schema_name, &self.topic,
+            message_id, &self.connector_name, &self.schema_name, &topic,
         );
 
-        Ok(())
+        Ok(Published { topic, message_id })
     }
 
     async fn change_stream(&self) -> anyhow::Result<CStream> {
+        let collections = match &self.collections {
+            Some(collections) => collections.clone(),
+            None => {
+                self.db
+                    .list_collection_names(doc! {
+                        "name": {
+                            "$not": { "$regex": "^system\\." },
+                            "$nin": self.internal_collections.clone(),
+                        },
+                        // views and time series collections don't support pre-images
+                        "type": "collection",
+                    })
+                    .await?
+            }
+        };
+
         // enable support for full document before and after change
         // used to obtain the document for delete events
         // https://docs.mongodb.com/manual/reference/command/collMod/#dbcmd.collMod
-        self.db
-            .run_command(
-                doc! {
-                    "collMod": self.db_collection.clone(),
-                    "changeStreamPreAndPostImages": doc! {
-                        "enabled": true,
-                    }
-                },
-                None,
-            )
-            .await
-            .map_err(|err| {
-                anyhow!(
-                    "failed to enable full document support for stream: {}, {}",
-                    &self.connector_name,
-                    err
+        for coll in collections {
+            self.db
+                .run_command(
+                    doc! {
+                        "collMod": coll,
+                        "changeStreamPreAndPostImages": doc! {
+                            "enabled": true,
+                        }
+                    },
+                    None,
                 )
-            })?;
-
-        let coll = self.db.collection::<Document>(&self.db_collection);
+                .await
+                .map_err(|err| {
+                    anyhow!(
+                        "failed to enable full document support for stream: {}, {}",
+                        &self.connector_name,
+                        err
+                    )
+                })?;
+        }
 
         let opts = ChangeStreamOptions::builder()
             .full_document(Some(FullDocumentType::UpdateLookup))
             .full_document_before_change(Some(FullDocumentBeforeChangeType::WhenAvailable))
             .start_after(self.resume_token.clone())
+            .max_await_time(self.polling.max_await_time())
             .build();
 
-        Ok(coll.watch(None, Some(opts)).await?)
+        let mut pipeline = Vec::new();
+        match &self.collections {
+            None if !self.internal_collections.is_empty() => {
+                let internal = self.internal_collections.clone();
+                pipeline.push(doc! { "$match": { "ns.coll": { "$nin": internal } } });
+            }
+            Some(collections) if collections.len() > 1 => {
+                pipeline.push(doc! { "$match": { "ns.coll": { "$in": collections.clone() } } });
+            }
+            _ => {}
+        }
+        pipeline.extend(self.pipeline.iter().cloned());
+        if let Some(fields) = &self.fields {
+            let projection = projection(fields, self.event_time.as_ref());
+            pipeline.push(doc! { "$project": projection });
+        }
+
+        let cs = match self.single_collection() {
+            Some(coll) => {
+                let coll = self.db.collection::<Document>(coll);
+                coll.watch(pipeline, Some(opts)).await
+            }
+            None => self.db.watch(pipeline, Some(opts)).await,
+        };
+
+        match (cs, &self.resume_token) {
+            (Ok(cs), _) => Ok(cs),
+            (Err(err), Some(_)) => Err(anyhow!(
+                "failed to resume the change stream, the resume token may be outside the oplog window: {}. stream: {}",
+                err,
+                &self.connector_name
+            )),
+            (Err(err), None) => Err(err.into()),
+        }
     }
 }
 
 async fn get_schema_service<P>(
+    endpoint: &str,
     provider_name: SchemaProviderName,
     auth_interceptor: ServiceAccountAuth<P>,
     db: Database,
@@ -233,19 +1540,49 @@ where
 {
     Ok(match provider_name {
         SchemaProviderName::Gcp => {
-            Box::new(SchemaService::with_interceptor(auth_interceptor).await?)
+            Box::new(SchemaService::with_endpoint(endpoint, auth_interceptor).await?)
         }
         SchemaProviderName::MongoDB => Box::new(MongoDbSchemaProvider::new(db).await),
     })
 }
 
+/// Resolves the avro schema attached to the topic
+async fn get_topic_schema<P>(
+    endpoint: &str,
+    auth_interceptor: ServiceAccountAuth<P>,
+    topic: String,
+) -> anyhow::Result<Schema>
+where
+    P: GCPTokenProvider + Clone + 'static + Send + Sync,
+{
+    let settings = PubSubPublisher::with_endpoint(endpoint, auth_interceptor.clone())
+        .await?
+        .topic_schema_settings(topic.clone())
+        .await?
+        .ok_or_else(|| anyhow!("no schema is attached to topic: {}", topic))?;
+
+    if settings.encoding != Encoding::Binary as i32 {
+        bail!(
+            "topic {} expects {:?} encoded messages, only binary encoding is supported",
+            topic,
+            Encoding::from_i32(settings.encoding).unwrap_or(Encoding::Unspecified)
+        );
+    }
+
+    SchemaService::with_endpoint(endpoint, auth_interceptor)
+        .await?
+        .get_schema(settings.schema)
+        .await
+}
+
 async fn get_publisher_service<P>(
+    endpoint: &str,
     auth_interceptor: ServiceAccountAuth<P>,
 ) -> anyhow::Result<Box<dyn EventSink + Send + Sync>>
 where
     P: GCPTokenProvider + Clone + 'static + Send + Sync,
 {
     Ok(Box::new(
-        PubSubPublisher::with_interceptor(auth_interceptor).await?,
+        PubSubPublisher::with_endpoint(endpoint, auth_interceptor).await?,
     ))
 }
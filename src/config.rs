@@ -1,20 +1,130 @@
+use anyhow::{anyhow, bail, Context};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use mongodb::bson::Document;
 use serde_derive::Deserialize;
+use toml::Value;
+use toml_edit::ImDocument;
 
 #[derive(Deserialize, Debug, Clone, Default)]
 pub struct Config {
     #[serde(rename = "gcp_service_account_key_path")]
     pub gcp_serv_acc_key_path: String,
+    #[serde(default)]
+    pub notifications: NotificationsCfg,
+    pub lineage: Option<LineageCfg>,
+    #[serde(default)]
+    pub runtime: RuntimeCfg,
+    #[serde(default)]
+    pub logging: LoggingCfg,
     pub connectors: Vec<Connector>,
 }
 
+/// LoggingCfg configures how secrets are kept out of the logs
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LoggingCfg {
+    /// Regex patterns whose matches are masked in log messages,
+    /// in addition to the credentials of connection strings
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+/// RuntimeCfg tunes the tokio runtime, the tokio defaults apply if unset
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RuntimeCfg {
+    /// Number of threads running the async tasks, defaults to the number of cpu cores
+    pub worker_threads: Option<usize>,
+    /// Upper bound of the threads running blocking work such as avro encoding
+    pub max_blocking_threads: Option<usize>,
+}
+
+impl RuntimeCfg {
+    /// Tokio panics on zero threads, so zero values are rejected upfront
+    fn validate(&self) -> anyhow::Result<()> {
+        for (key, threads) in [
+            ("worker_threads", self.worker_threads),
+            ("max_blocking_threads", self.max_blocking_threads),
+        ] {
+            if threads == Some(0) {
+                bail!("'runtime.{}' must be greater than 0", key);
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Connector {
     pub name: String,
     pub db_connection: String,
     pub db_name: String,
-    pub db_collection: String,
+    /// A collection name, a list of collection names, or `*` for the whole database
+    pub db_collection: CollectionSelector,
     pub schema: SchemaCfg,
+    /// Document fields projected server side, the whole document is read if unset
+    pub fields: Option<Vec<String>>,
+    /// Aggregation stages applied to the change stream server side, before the field projection
+    #[serde(default)]
+    pub pipeline: Vec<Document>,
+    /// Can reference the event namespace with `{database}` and `{collection}`
     pub topic: String,
+    /// Static attributes added to every message, event attributes of the same name take precedence.
+    /// Values can reference the event namespace with `{database}` and `{collection}`
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+    /// Messages without a non-empty value for each of these attributes are not published
+    #[serde(default)]
+    pub required_attributes: Vec<String>,
+    /// Pubsub endpoint, e.g. a regional `https://europe-west1-pubsub.googleapis.com`.
+    /// Defaults to the global endpoint
+    pub pubsub_endpoint: Option<String>,
+    /// Check on startup that the connector schema matches the schema attached to the topic
+    #[serde(default)]
+    pub validate_topic_schema: bool,
+    /// Collection in the connector database to record published message ids in
+    pub delivery_log: Option<String>,
+    /// Resumes the change stream after the last event recorded in the delivery log,
+    /// so that delivered events are not published again on restart
+    #[serde(default)]
+    pub skip_delivered: bool,
+    /// What happens to documents that can't be encoded, skipped by default
+    #[serde(default)]
+    pub on_encode_error: EncodeErrorPolicy,
+    /// Compresses the encoded payload
+    pub compression: Option<Compression>,
+    /// Emit the changed fields of update events
+    pub update_diff: Option<UpdateDiff>,
+    #[serde(default)]
+    pub polling: PollingCfg,
+    pub event_time: Option<EventTimeCfg>,
+    pub fallback: Option<FallbackCfg>,
+    pub poll_fallback: Option<PollFallbackCfg>,
+    /// Runs a query on a schedule instead of watching the collection
+    pub schedule: Option<ScheduleCfg>,
+    #[serde(default)]
+    pub mongodb: MongoDbCfg,
+    /// Overrides the global notification settings for this connector
+    pub notifications: Option<NotificationsCfg>,
+}
+
+impl Connector {
+    /// Returns the watched collections, `None` for the whole database
+    pub fn collections(&self) -> Option<Vec<String>> {
+        match &self.db_collection {
+            CollectionSelector::Name(name) if name == "*" => None,
+            CollectionSelector::Name(name) => Some(vec![name.clone()]),
+            CollectionSelector::List(names) => Some(names.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CollectionSelector {
+    Name(String),
+    List(Vec<String>),
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -30,9 +140,755 @@ pub enum SchemaProviderName {
     MongoDB,
 }
 
+/// PollingCfg tunes how the change stream is polled
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PollingCfg {
+    /// Maximum time the server waits for new events before an empty batch is returned
+    pub max_await_time_ms: Option<u64>,
+    /// Initial pause after an empty poll, doubled on every consecutive empty poll.
+    /// No pause is made if unset
+    pub idle_backoff_ms: Option<u64>,
+    /// Upper bound for the idle pause
+    pub max_idle_backoff_ms: Option<u64>,
+}
+
+impl PollingCfg {
+    pub fn max_await_time(&self) -> Option<Duration> {
+        self.max_await_time_ms.map(Duration::from_millis)
+    }
+
+    /// Returns the initial and the maximum idle pause
+    pub fn idle_backoff(&self) -> Option<(Duration, Duration)> {
+        let min = Duration::from_millis(self.idle_backoff_ms?);
+        let max = self
+            .max_idle_backoff_ms
+            .map(Duration::from_millis)
+            .unwrap_or(min)
+            .max(min);
+
+        Some((min, max))
+    }
+}
+
+/// EventTimeCfg designates the document field stamped as the `event_time` attribute
+#[derive(Deserialize, Debug, Clone)]
+pub struct EventTimeCfg {
+    /// Dotted path to the field, e.g. `meta.updated_at`
+    pub field: String,
+    /// Event times ahead of the processing time by up to this are accepted as is,
+    /// larger values are clamped to the processing time
+    #[serde(default)]
+    pub max_clock_skew_secs: u64,
+    /// Events older than this when processed are counted as late arrivals
+    pub late_after_secs: Option<u64>,
+}
+
+/// MongoDbCfg overrides the client defaults of the connector database.
+/// Applies to the change stream, the delivery log and the mongodb schema provider
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MongoDbCfg {
+    pub write_concern: Option<WriteConcernCfg>,
+    pub read_preference: Option<ReadPreferenceMode>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WriteConcernCfg {
+    pub w: Option<WriteAcknowledgment>,
+    pub journal: Option<bool>,
+    pub wtimeout_ms: Option<u64>,
+}
+
+/// WriteAcknowledgment is either a number of nodes, `majority` or a custom tag set name
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum WriteAcknowledgment {
+    Nodes(u32),
+    Tag(String),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum ReadPreferenceMode {
+    Primary,
+    PrimaryPreferred,
+    Secondary,
+    SecondaryPreferred,
+    Nearest,
+}
+
+/// PollFallbackCfg reads the collection with periodic queries where change streams
+/// are not available, e.g. on standalone servers
+#[derive(Deserialize, Debug, Clone)]
+pub struct PollFallbackCfg {
+    /// Ascending field the new documents are read by, `_id` by default
+    #[serde(default = "default_watermark_field")]
+    pub watermark_field: String,
+    #[serde(default = "default_poll_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_poll_batch_size")]
+    pub batch_size: i64,
+    /// Polls even if change streams are available
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl PollFallbackCfg {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+fn default_watermark_field() -> String {
+    "_id".to_owned()
+}
+
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_poll_batch_size() -> i64 {
+    100
+}
+
+/// ScheduleCfg exports the result of a query on a cron schedule, e.g. for periodic
+/// exports that don't need change events
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScheduleCfg {
+    /// Cron expression with seconds, e.g. `0 0 * * * *` for every hour, evaluated in UTC
+    pub cron: String,
+    /// Find filter, all documents are read if neither a filter nor aggregate stages are set
+    pub filter: Option<Document>,
+    /// Aggregation stages, can't be combined with a filter
+    pub aggregate: Option<Vec<Document>>,
+}
+
+/// FallbackCfg declares the topics an event is published to, in order,
+/// once publishing to the connector topic failed
+#[derive(Deserialize, Debug, Clone)]
+pub struct FallbackCfg {
+    pub topics: Vec<String>,
+    /// Publish attempts per topic after the first failed one
+    #[serde(default = "default_publish_retries")]
+    pub retries: u32,
+    /// Pause between the publish attempts
+    #[serde(default = "default_publish_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+}
+
+/// Publish attempts after the first failed one, also used without a fallback
+pub const PUBLISH_RETRIES: u32 = 3;
+pub const PUBLISH_RETRY_BACKOFF_MS: u64 = 500;
+
+fn default_publish_retries() -> u32 {
+    PUBLISH_RETRIES
+}
+
+fn default_publish_retry_backoff_ms() -> u64 {
+    PUBLISH_RETRY_BACKOFF_MS
+}
+
+impl FallbackCfg {
+    pub fn retry_backoff(&self) -> Duration {
+        Duration::from_millis(self.retry_backoff_ms)
+    }
+}
+
+/// EncodeErrorPolicy decides what happens to documents that can't be encoded
+/// with the connector schema
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "policy", rename_all = "lowercase")]
+pub enum EncodeErrorPolicy {
+    /// The document is skipped and counted
+    #[default]
+    Skip,
+    /// The document is published as relaxed extended json to the topic, with the error attached
+    Deadletter { topic: String },
+    /// The connector stops
+    Fail,
+}
+
+/// Compression applied to the payload after encoding, announced by the `content_encoding` attribute
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+}
+
+/// UpdateDiff controls how the changed fields of an update event are published
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateDiff {
+    /// The diff is added as a json `diff` attribute alongside the avro encoded document,
+    /// diffs over the attribute size limit are truncated
+    Attribute,
+    /// The diff is published as a json payload instead of the avro encoded document.
+    /// Can't be combined with topic schema validation
+    Payload,
+}
+
+/// LineageCfg configures where OpenLineage run events are sent to
+#[derive(Deserialize, Debug, Clone)]
+pub struct LineageCfg {
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct NotificationsCfg {
+    #[serde(default)]
+    pub webhooks: Vec<WebhookCfg>,
+    /// Notify when the change stream lags behind the cluster time by more than this
+    pub lag_threshold_secs: Option<u64>,
+    /// Notify when the share of failed events exceeds this, e.g. `0.05`
+    pub max_error_rate: Option<f64>,
+    /// Number of the most recent events the error rate is computed over, 100 by default
+    pub error_rate_window: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookCfg {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    Slack,
+    #[default]
+    Generic,
+}
+
 impl Config {
+    /// Loads the config from a toml, json or yaml file, detected by the extension, and resolves
+    /// `${VAR}` and `${VAR:-default}` references to environment variables in all string values
     pub fn load(path: &str) -> anyhow::Result<Self> {
         let cfg = std::fs::read_to_string(path)?;
-        Ok(toml::from_str(&cfg)?)
+        let format = ConfigFormat::from_path(path)?;
+        Self::parse_format(&cfg, format, &|name| std::env::var(name).ok())
+            .with_context(|| format!("invalid config {}", path))
+    }
+
+    fn parse_format<F>(src: &str, format: ConfigFormat, lookup: &F) -> anyhow::Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut value = match format {
+            ConfigFormat::Toml => return Self::parse(src, lookup),
+            ConfigFormat::Json => serde_json::from_str::<serde_json::Value>(src)?,
+            ConfigFormat::Yaml => serde_yaml::from_str::<serde_json::Value>(src)?,
+        };
+
+        // toml has no null, keys set to null or left empty are treated as unset
+        strip_nulls(&mut value);
+        Self::from_value(Value::try_from(value)?, None, lookup)
+    }
+
+    fn parse<F>(src: &str, lookup: &F) -> anyhow::Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::from_value(toml::from_str(src)?, Some(src), lookup)
+    }
+
+    /// Resolves the variables and deserializes the config.
+    /// The toml source is used to point errors to a line
+    fn from_value<F>(mut value: Value, toml_src: Option<&str>, lookup: &F) -> anyhow::Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        interpolate_value(&mut value, "", lookup)?;
+
+        let cfg: Self = serde_path_to_error::deserialize(value).map_err(|err| {
+            let path = err.path().to_string();
+            // the toml key suffix is superseded by the full path
+            let msg = err.inner().to_string();
+            let msg = msg.split(" for key `").next().unwrap_or_default();
+
+            match toml_src.and_then(|src| key_line(src, &path)) {
+                Some(line) => anyhow!("'{}' at line {}: {}", path, line, msg),
+                None => anyhow!("'{}': {}", path, msg),
+            }
+        })?;
+
+        cfg.runtime.validate()?;
+        Ok(cfg)
+    }
+
+    /// Returns the notification settings applied to a connector
+    pub fn notifications_for(&self, connector: &Connector) -> NotificationsCfg {
+        connector
+            .notifications
+            .clone()
+            .unwrap_or_else(|| self.notifications.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &str) -> anyhow::Result<Self> {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+
+        match ext {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            _ => bail!(
+                "unsupported config format: {}. expected toml, json or yaml",
+                path
+            ),
+        }
+    }
+}
+
+fn interpolate_value<F>(value: &mut Value, path: &str, lookup: &F) -> anyhow::Result<()>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match value {
+        Value::String(s) => {
+            *s = interpolate(s, lookup).with_context(|| format!("failed to resolve '{}'", path))?
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate_value(item, &format!("{}[{}]", path, i), lookup)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let path = match path.is_empty() {
+                    true => key.clone(),
+                    false => format!("{}.{}", path, key),
+                };
+                interpolate_value(item, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Returns the line of the value at a deserialization path, e.g. `connectors[1].schema.provider`.
+/// Falls back to the closest parent with a position in the source
+fn key_line(src: &str, path: &str) -> Option<usize> {
+    let doc = ImDocument::parse(src).ok()?;
+    let mut item = doc.as_item();
+    let mut span = None;
+
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let (key, index) = match segment.split_once('[') {
+            Some((key, index)) => (key, index.trim_end_matches(']').parse::<usize>().ok()),
+            None => (segment, None),
+        };
+
+        let Some(next) = item.get(key) else {
+            break;
+        };
+        item = next;
+        span = item.span().or(span);
+
+        if let Some(index) = index {
+            let Some(next) = item.get(index) else {
+                break;
+            };
+            item = next;
+            span = item.span().or(span);
+        }
+    }
+
+    span.map(|span| src[..span.start].matches('\n').count() + 1)
+}
+
+/// Replaces `${VAR}` and `${VAR:-default}` with the variable value.
+/// `$${` is kept as a literal `${`
+fn interpolate<F>(s: &str, lookup: &F) -> anyhow::Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+            continue;
+        }
+
+        if !rest.starts_with("${") {
+            result.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let end = rest
+            .find('}')
+            .ok_or_else(|| anyhow!("unterminated variable reference: {}", rest))?;
+        let expr = &rest[2..end];
+
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+
+        if name.is_empty() {
+            bail!("empty variable name in: {}", s);
+        }
+
+        // an empty variable is a value of its own, only the default form treats it as unset
+        match (lookup(name), default) {
+            (Some(v), Some(default)) if v.is_empty() => result.push_str(default),
+            (Some(v), _) => result.push_str(&v),
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => bail!("environment variable '{}' is not set", name),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, val| !val.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        interpolate, interpolate_value, Config, ConfigFormat, EncodeErrorPolicy, PUBLISH_RETRIES,
+        PUBLISH_RETRY_BACKOFF_MS,
+    };
+    use toml::Value;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "DB_HOST" => Some("mongo1".to_owned()),
+            "EMPTY" => Some("".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolate_variables_and_defaults() -> anyhow::Result<()> {
+        assert_eq!(
+            "mongodb://mongo1:27017",
+            interpolate("mongodb://${DB_HOST}:${DB_PORT:-27017}", &lookup)?
+        );
+        assert_eq!("fallback", interpolate("${EMPTY:-fallback}", &lookup)?);
+        assert_eq!("prefix-", interpolate("prefix-${EMPTY}", &lookup)?);
+        assert_eq!("${DB_HOST} $5", interpolate("$${DB_HOST} $5", &lookup)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn interpolate_reports_missing_variable_path() {
+        let mut value: Value = toml::from_str(
+            r#"
+            [[connectors]]
+            name = "connector 1"
+            topic = "projects/${GCP_PROJECT}/topics/employees"
+        "#,
+        )
+        .unwrap();
+
+        let err = interpolate_value(&mut value, "", &lookup).unwrap_err();
+        assert_eq!(
+            "failed to resolve 'connectors[0].topic': environment variable 'GCP_PROJECT' is not set",
+            format!("{:#}", err)
+        );
+    }
+
+    #[test]
+    fn parse_reports_invalid_key_path_and_line() {
+        let src = r#"
+gcp_service_account_key_path = "key.json"
+
+[[connectors]]
+name = "connector 1"
+db_connection = "mongodb://localhost:27017"
+db_name = "mydb"
+db_collection = "employees"
+schema = { provider = "gcp", id = "schema" }
+topic = "topic"
+
+[[connectors]]
+name = "connector 2"
+db_connection = "mongodb://localhost:27017"
+db_name = "mydb"
+db_collection = "employees"
+schema = { provider = "gpc", id = "schema" }
+topic = "topic"
+"#;
+
+        let err = Config::parse(src, &lookup).unwrap_err();
+        assert_eq!(
+            "'connectors[1].schema.provider' at line 17: unknown variant `gpc`, expected `gcp` or `mongodb`",
+            err.to_string()
+        );
+        let src = r#"
+gcp_service_account_key_path = "key.json"
+
+[[connectors]]
+name = "connector 1"
+db_connection = "mongodb://localhost:27017"
+db_name = "mydb"
+db_collection = [
+    "employees",
+    "projects",
+]
+topic = "topic"
+
+[connectors.schema]
+id = "schema"
+provider = "gpc"
+"#;
+
+        let err = Config::parse(src, &lookup).unwrap_err();
+        assert_eq!(
+            "'connectors[0].schema.provider' at line 16: unknown variant `gpc`, expected `gcp` or `mongodb`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_zero_runtime_threads() {
+        let src = r#"
+gcp_service_account_key_path = "key.json"
+connectors = []
+
+[runtime]
+worker_threads = 4
+max_blocking_threads = 0
+"#;
+
+        let err = Config::parse(src, &lookup).unwrap_err();
+        assert_eq!(
+            "'runtime.max_blocking_threads' must be greater than 0",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_json_and_yaml() -> anyhow::Result<()> {
+        let json = r#"{
+            "gcp_service_account_key_path": "key.json",
+            "connectors": [{
+                "name": "connector 1",
+                "db_connection": "mongodb://${DB_HOST}:27017",
+                "db_name": "mydb",
+                "db_collection": "*",
+                "schema": { "provider": "mongodb", "id": "schema" },
+                "topic": "topic"
+            }]
+        }"#;
+
+        let yaml = r#"
+gcp_service_account_key_path: key.json
+connectors:
+  - name: connector 1
+    db_connection: mongodb://${DB_HOST}:27017
+    db_name: mydb
+    db_collection: "*"
+    schema: { provider: mongodb, id: schema }
+    topic: topic
+"#;
+
+        for (src, format) in [(json, ConfigFormat::Json), (yaml, ConfigFormat::Yaml)] {
+            let cfg = Config::parse_format(src, format, &lookup)?;
+            assert_eq!("mongodb://mongo1:27017", cfg.connectors[0].db_connection);
+            assert_eq!(None, cfg.connectors[0].collections());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_null_as_unset() -> anyhow::Result<()> {
+        let json = r#"{
+            "gcp_service_account_key_path": "key.json",
+            "lineage": null,
+            "connectors": [{
+                "name": "connector 1",
+                "db_connection": "mongodb://localhost:27017",
+                "db_name": "mydb",
+                "db_collection": "employees",
+                "schema": { "provider": "mongodb", "id": "schema" },
+                "topic": "topic",
+                "fields": null
+            }]
+        }"#;
+
+        let yaml = r#"
+gcp_service_account_key_path: key.json
+lineage:
+connectors:
+  - name: connector 1
+    db_connection: mongodb://localhost:27017
+    db_name: mydb
+    db_collection: employees
+    schema: { provider: mongodb, id: schema }
+    topic: topic
+    fields: ~
+"#;
+
+        for (src, format) in [(json, ConfigFormat::Json), (yaml, ConfigFormat::Yaml)] {
+            let cfg = Config::parse_format(src, format, &lookup)?;
+            assert!(cfg.lineage.is_none());
+            assert_eq!(None, cfg.connectors[0].fields);
+        }
+
+        Ok(())
+    }
+
+    /// Keys of a minimal valid connector
+    const BASE_CONNECTOR: [(&str, &str); 6] = [
+        ("name", r#""connector""#),
+        ("db_connection", r#""mongodb://localhost:27017""#),
+        ("db_name", r#""mydb""#),
+        ("db_collection", r#""employees""#),
+        ("schema", r#"{ provider = "mongodb", id = "schema" }"#),
+        ("topic", r#""topic""#),
+    ];
+
+    /// A `[[connectors]]` table of the base connector, `keys` are added or replace base keys
+    fn connector(keys: &[(&str, &str)]) -> String {
+        let base = BASE_CONNECTOR
+            .iter()
+            .filter(|(key, _)| !keys.iter().any(|(k, _)| k == key));
+
+        let mut table = "[[connectors]]\n".to_owned();
+        for (key, value) in base.chain(keys) {
+            table.push_str(&format!("{} = {}\n", key, value));
+        }
+        table
+    }
+
+    fn config_src(connectors: &[String]) -> String {
+        format!(
+            "gcp_service_account_key_path = \"key.json\"\n\n{}",
+            connectors.join("\n")
+        )
+    }
+
+    #[test]
+    fn parse_collection_selector() -> anyhow::Result<()> {
+        let src = config_src(&[
+            connector(&[("db_collection", r#""employees""#)]),
+            connector(&[("db_collection", r#"["employees", "projects"]"#)]),
+            connector(&[("db_collection", r#""*""#)]),
+        ]);
+
+        let cfg = Config::parse(&src, &lookup)?;
+        assert_eq!(
+            Some(vec!["employees".to_owned()]),
+            cfg.connectors[0].collections()
+        );
+        assert_eq!(
+            Some(vec!["employees".to_owned(), "projects".to_owned()]),
+            cfg.connectors[1].collections()
+        );
+        assert_eq!(None, cfg.connectors[2].collections());
+
+        let missing = src.replace("db_collection = \"*\"\n", "");
+        let err = Config::parse(&missing, &lookup).unwrap_err();
+        assert!(err.to_string().contains("missing field `db_collection`"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_pipeline() -> anyhow::Result<()> {
+        let src = config_src(&[connector(&[(
+            "pipeline",
+            r#"[{ "$match" = { operationType = { "$in" = ["insert", "update"] } } }]"#,
+        )])]);
+
+        let cfg = Config::parse(&src, &lookup)?;
+        assert_eq!(
+            vec![
+                mongodb::bson::doc! { "$match": { "operationType": { "$in": ["insert", "update"] } } }
+            ],
+            cfg.connectors[0].pipeline
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_schedule() -> anyhow::Result<()> {
+        let src = config_src(&[connector(&[(
+            "schedule",
+            r#"{ cron = "0 0 2 * * *", aggregate = [{ "$match" = { status = "active" } }] }"#,
+        )])]);
+
+        let cfg = Config::parse(&src, &lookup)?;
+        let schedule = cfg.connectors[0].schedule.as_ref().unwrap();
+        assert_eq!("0 0 2 * * *", schedule.cron);
+        assert_eq!(None, schedule.filter);
+        assert_eq!(
+            Some(vec![
+                mongodb::bson::doc! { "$match": { "status": "active" } }
+            ]),
+            schedule.aggregate
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_encode_error_policy() -> anyhow::Result<()> {
+        let src = config_src(&[
+            connector(&[(
+                "on_encode_error",
+                r#"{ policy = "deadletter", topic = "deadletter" }"#,
+            )]),
+            connector(&[]),
+        ]);
+
+        let cfg = Config::parse(&src, &lookup)?;
+        assert_eq!(
+            EncodeErrorPolicy::Deadletter {
+                topic: "deadletter".to_owned()
+            },
+            cfg.connectors[0].on_encode_error
+        );
+        assert_eq!(EncodeErrorPolicy::Skip, cfg.connectors[1].on_encode_error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_fallback_keeps_default_retries() -> anyhow::Result<()> {
+        let src = config_src(&[connector(&[("fallback", r#"{ topics = ["holding"] }"#)])]);
+
+        let cfg = Config::parse(&src, &lookup)?;
+        let fallback = cfg.connectors[0].fallback.as_ref().unwrap();
+        assert_eq!(PUBLISH_RETRIES, fallback.retries);
+        assert_eq!(PUBLISH_RETRY_BACKOFF_MS, fallback.retry_backoff_ms);
+
+        Ok(())
     }
 }
@@ -0,0 +1,278 @@
+use std::any::Any;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context};
+use gauth::{serv_account::ServiceAccount, token_provider::AsyncTokenProvider};
+use log::{error, info, warn};
+use tokio::time::sleep;
+
+use crate::config::Config;
+use crate::notification::{JobEvent, Notifier};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// The restart backoff is reset once the app ran longer than this
+const STABLE_RUN: Duration = Duration::from_secs(300);
+/// Restarts without a stable run in between that are notified as a restart loop
+pub(crate) const RESTART_LOOP: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct DaemonCfg {
+    pub config_path: String,
+    pub pid_file: PathBuf,
+    pub crash_report_dir: PathBuf,
+}
+
+/// Runs the app under supervision: the app is restarted with a backoff after a panic
+/// or an error, and a crash report is written for every restart.
+/// The pid file and the token provider are held for the lifetime of the daemon.
+/// Restarts are notified to the global webhooks of the config the daemon started with
+pub async fn run(cfg: DaemonCfg) -> anyhow::Result<()> {
+    let _pid_file = PidFile::create(&cfg.pid_file)?;
+    let config = Config::load(&cfg.config_path)?;
+    let notifier = Notifier::new(config.notifications.clone())?;
+    // the token refresh can't be stopped, so the provider is shared by the app restarts
+    let tp = crate::token_provider(&config).await;
+    let mut backoff = RestartBackoff::new();
+    let mut restarts = 0;
+
+    loop {
+        let started_at = Instant::now();
+        let app = tokio::spawn(run_app(cfg.config_path.clone(), tp.clone()));
+        let abort_handle = app.abort_handle();
+
+        let reason = tokio::select! {
+            result = app => match result {
+                Ok(Ok(())) => {
+                    info!("mstream exited");
+                    return Ok(());
+                }
+                Ok(Err(err)) => format!("error: {:#}", err),
+                Err(err) if err.is_panic() => format!("panic: {}", panic_message(err.into_panic())),
+                Err(err) => format!("app task failed: {}", err),
+            },
+            _ = shutdown_signal() => {
+                info!("received shutdown signal, stopping mstream daemon");
+                abort_handle.abort();
+                return Ok(());
+            }
+        };
+
+        let delay = backoff.next(started_at.elapsed());
+        restarts += 1;
+        error!(
+            "mstream crashed, restart #{} in {:?}: {}",
+            restarts, delay, reason
+        );
+
+        if let Err(err) = write_crash_report(&cfg.crash_report_dir, restarts, &reason) {
+            warn!("failed to write crash report: {:#}", err);
+        }
+        notifier.notify(JobEvent::AppRestarted { restarts, reason });
+
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown_signal() => {
+                info!("received shutdown signal, stopping mstream daemon");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// RestartBackoff doubles the delay between restarts, the delay is reset after a stable run
+pub(crate) struct RestartBackoff {
+    next: Duration,
+    /// Restarts since the last stable run
+    streak: u32,
+}
+
+impl RestartBackoff {
+    pub(crate) fn new() -> Self {
+        Self {
+            next: MIN_BACKOFF,
+            streak: 0,
+        }
+    }
+
+    pub(crate) fn streak(&self) -> u32 {
+        self.streak
+    }
+
+    /// Returns the delay before restarting a run that lasted `run_time`
+    pub(crate) fn next(&mut self, run_time: Duration) -> Duration {
+        if run_time > STABLE_RUN {
+            self.next = MIN_BACKOFF;
+            self.streak = 0;
+        }
+
+        self.streak += 1;
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = sigterm.recv() => {}
+            }
+        }
+        Err(err) => {
+            warn!("failed to listen for SIGTERM: {}", err);
+            _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    _ = tokio::signal::ctrl_c().await;
+}
+
+/// Failed connectors are restarted by the app itself, the daemon restarts the app
+/// when it couldn't start or all of its connectors exited
+async fn run_app(
+    config_path: String,
+    tp: AsyncTokenProvider<ServiceAccount>,
+) -> anyhow::Result<()> {
+    let config = Config::load(&config_path)?;
+    crate::run_streams(config, tp, true).await
+}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic payload".to_owned()
+    }
+}
+
+fn write_crash_report(dir: &Path, restarts: u32, reason: &str) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dir.join(format!("crash-{}.log", timestamp));
+
+    let report = format!(
+        "timestamp: {}\npid: {}\nrestart: {}\nversion: {}\nreason: {}\n",
+        timestamp,
+        std::process::id(),
+        restarts,
+        env!("CARGO_PKG_VERSION"),
+        reason,
+    );
+
+    fs::write(&path, report).with_context(|| format!("path: {}", path.display()))?;
+    info!("crash report written to {}", path.display());
+
+    Ok(())
+}
+
+/// PidFile holds the process id of the running daemon and is removed on drop
+struct PidFile(PathBuf);
+
+impl PidFile {
+    fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Ok(content) = fs::read_to_string(path) {
+            match content.trim().parse::<u32>() {
+                // a container restart may hand out the pid of the previous run to this process
+                Ok(pid) if pid != std::process::id() && is_running(pid) => bail!(
+                    "mstream is already running with pid {}. pid file: {}",
+                    pid,
+                    path.display()
+                ),
+                _ => warn!(
+                    "removing stale pid file {} of pid {}",
+                    path.display(),
+                    content.trim()
+                ),
+            }
+            fs::remove_file(path).map_err(|err| {
+                anyhow!(
+                    "failed to remove stale pid file {}: {}",
+                    path.display(),
+                    err
+                )
+            })?;
+        }
+
+        // create_new fails if another instance created the file in the meantime
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|err| anyhow!("failed to create pid file {}: {}", path.display(), err))?;
+        let pid_file = Self(path.to_path_buf());
+
+        write!(file, "{}", std::process::id())
+            .map_err(|err| anyhow!("failed to write pid file {}: {}", path.display(), err))?;
+
+        Ok(pid_file)
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            warn!("failed to remove pid file {}: {}", self.0.display(), err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Without a portable way to probe processes an existing pid file is treated as stale
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_backoff_doubles_and_resets_after_stable_run() {
+        let mut backoff = RestartBackoff::new();
+        let short_run = Duration::from_secs(1);
+
+        let delays: Vec<_> = (0..8).map(|_| backoff.next(short_run)).collect();
+        assert_eq!(delays[0], MIN_BACKOFF);
+        assert_eq!(delays[1], MIN_BACKOFF * 2);
+        assert_eq!(delays[7], MAX_BACKOFF);
+        assert_eq!(backoff.streak(), 8);
+
+        assert_eq!(backoff.next(STABLE_RUN * 2), MIN_BACKOFF);
+        assert_eq!(backoff.streak(), 1);
+    }
+
+    #[test]
+    fn pid_file_of_own_pid_is_stale() {
+        let path = std::env::temp_dir().join(format!("mstream-test-{}.pid", std::process::id()));
+        fs::write(&path, std::process::id().to_string()).unwrap();
+
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
@@ -1,9 +1,48 @@
-use mongodb::options::ClientOptions;
+use std::time::Duration;
+
+use mongodb::options::{
+    Acknowledgment, ClientOptions, ReadPreference, ReadPreferenceOptions, SelectionCriteria,
+    WriteConcern,
+};
 use mongodb::Client;
 
-pub async fn db_client(name: String, conn_str: &str) -> anyhow::Result<Client> {
+use crate::config::{MongoDbCfg, ReadPreferenceMode, WriteAcknowledgment};
+
+pub async fn db_client(name: String, conn_str: &str, cfg: &MongoDbCfg) -> anyhow::Result<Client> {
     let mut opts = ClientOptions::parse(conn_str).await?;
     opts.app_name = Some(name);
 
+    if let Some(wc) = &cfg.write_concern {
+        opts.write_concern = Some(
+            WriteConcern::builder()
+                .w(wc.w.clone().map(acknowledgment))
+                .journal(wc.journal)
+                .w_timeout(wc.wtimeout_ms.map(Duration::from_millis))
+                .build(),
+        );
+    }
+
+    if let Some(mode) = cfg.read_preference {
+        opts.selection_criteria = Some(SelectionCriteria::ReadPreference(read_preference(mode)));
+    }
+
     Ok(Client::with_options(opts)?)
 }
+
+fn acknowledgment(w: WriteAcknowledgment) -> Acknowledgment {
+    match w {
+        WriteAcknowledgment::Nodes(n) => Acknowledgment::from(n),
+        WriteAcknowledgment::Tag(tag) => Acknowledgment::from(tag),
+    }
+}
+
+fn read_preference(mode: ReadPreferenceMode) -> ReadPreference {
+    let options = ReadPreferenceOptions::default();
+    match mode {
+        ReadPreferenceMode::Primary => ReadPreference::Primary,
+        ReadPreferenceMode::PrimaryPreferred => ReadPreference::PrimaryPreferred { options },
+        ReadPreferenceMode::Secondary => ReadPreference::Secondary { options },
+        ReadPreferenceMode::SecondaryPreferred => ReadPreference::SecondaryPreferred { options },
+        ReadPreferenceMode::Nearest => ReadPreference::Nearest { options },
+    }
+}
@@ -0,0 +1,73 @@
+use mongodb::bson::{doc, from_bson, DateTime, Document};
+use mongodb::change_stream::event::ResumeToken;
+use mongodb::options::FindOneOptions;
+use mongodb::{Collection, Database, IndexModel};
+use serde::Serialize;
+
+/// DeliveryEntry correlates a change stream event cursor with the published message id
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryEntry {
+    pub connector: String,
+    pub cursor: ResumeToken,
+    pub operation_type: String,
+    pub document_key: Option<Document>,
+    pub topic: String,
+    pub message_id: String,
+    pub published_at: DateTime,
+}
+
+/// DeliveryLog records published events in a mongodb collection,
+/// so it can be audited whether a change stream event was delivered
+pub struct DeliveryLog {
+    collection: Collection<DeliveryEntry>,
+}
+
+impl DeliveryLog {
+    pub async fn new(db: &Database, collection_name: &str) -> anyhow::Result<Self> {
+        let collection = db.collection::<DeliveryEntry>(collection_name);
+
+        collection
+            .create_indexes(
+                [
+                    IndexModel::builder()
+                        .keys(doc! { "connector": 1, "cursor": 1 })
+                        .build(),
+                    IndexModel::builder()
+                        .keys(doc! { "connector": 1, "_id": -1 })
+                        .build(),
+                ],
+                None,
+            )
+            .await?;
+
+        Ok(Self { collection })
+    }
+
+    pub fn collection_name(&self) -> &str {
+        self.collection.name()
+    }
+
+    /// Returns the cursor of the event the connector published last
+    pub async fn last_cursor(&self, connector: &str) -> anyhow::Result<Option<ResumeToken>> {
+        let opts = FindOneOptions::builder()
+            .sort(doc! { "_id": -1 })
+            .projection(doc! { "cursor": 1 })
+            .build();
+
+        let entry = self
+            .collection
+            .clone_with_type::<Document>()
+            .find_one(doc! { "connector": connector }, opts)
+            .await?;
+
+        match entry.and_then(|mut entry| entry.remove("cursor")) {
+            Some(cursor) => Ok(Some(from_bson(cursor)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn record(&self, entry: DeliveryEntry) -> anyhow::Result<()> {
+        self.collection.insert_one(entry, None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use mongodb::bson::{Bson, Document};
+use mongodb::change_stream::event::UpdateDescription;
+use serde_json::{json, Map, Value};
+
+/// Computes the changed fields of an update from its update description,
+/// the `before` values are read from the document pre-image if available.
+///
+/// Updated nested documents are compared field by field with the pre-image and reported with dotted paths,
+/// any other value (including arrays) is reported as a whole.
+/// Removed fields are reported with an `after` value of `null`, truncated arrays with their remaining items.
+pub fn changes(before: Option<&Document>, update: &UpdateDescription) -> Value {
+    let mut diff = Map::new();
+
+    for (path, after) in update.updated_fields.iter() {
+        match (before.and_then(|doc| get_path(doc, path)), after) {
+            (Some(Bson::Document(b)), Bson::Document(a)) => {
+                collect_changes(&format!("{}.", path), b, a, &mut diff);
+            }
+            (b, a) => {
+                diff.insert(path.clone(), change(b, Some(a)));
+            }
+        }
+    }
+
+    for path in update.removed_fields.iter() {
+        let b = before.and_then(|doc| get_path(doc, path));
+        diff.insert(path.clone(), change(b, None));
+    }
+
+    for truncated in update.truncated_arrays.iter().flatten() {
+        let b = before.and_then(|doc| get_path(doc, &truncated.field));
+        let a = match b {
+            Some(Bson::Array(items)) => Some(Bson::Array(
+                items
+                    .iter()
+                    .take(truncated.new_size.max(0) as usize)
+                    .cloned()
+                    .collect(),
+            )),
+            _ => None,
+        };
+        diff.insert(truncated.field.clone(), change(b, a.as_ref()));
+    }
+
+    Value::Object(diff)
+}
+
+fn collect_changes(
+    prefix: &str,
+    before: &Document,
+    after: &Document,
+    diff: &mut Map<String, Value>,
+) {
+    let removed = before
+        .keys()
+        .filter(|key| !after.contains_key(key.as_str()));
+
+    for key in after.keys().chain(removed) {
+        let path = format!("{}{}", prefix, key);
+
+        match (before.get(key), after.get(key)) {
+            (Some(Bson::Document(b)), Some(Bson::Document(a))) => {
+                collect_changes(&format!("{}.", path), b, a, diff);
+            }
+            (b, a) if b != a => {
+                diff.insert(path, change(b, a));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Resolves a dotted field path, array items are addressed by their index
+fn get_path<'a>(doc: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut val = doc.get(parts.next()?)?;
+
+    for part in parts {
+        val = match val {
+            Bson::Document(doc) => doc.get(part)?,
+            Bson::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    Some(val)
+}
+
+fn change(before: Option<&Bson>, after: Option<&Bson>) -> Value {
+    json!({ "before": to_json(before), "after": to_json(after) })
+}
+
+fn to_json(val: Option<&Bson>) -> Value {
+    val.cloned()
+        .map(Bson::into_relaxed_extjson)
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::changes;
+    use mongodb::bson::{doc, from_document};
+    use mongodb::change_stream::event::UpdateDescription;
+    use serde_json::json;
+
+    #[test]
+    fn changes_include_nested_added_and_removed_fields() -> anyhow::Result<()> {
+        let before = doc! {
+            "name": "Jon",
+            "age": 32,
+            "project": { "title": "A", "rating": 1.5 },
+            "nickname": "J",
+            "tags": ["a", "b", "c"],
+        };
+        let update: UpdateDescription = from_document(doc! {
+            "updatedFields": {
+                "age": 33,
+                "project": { "title": "A", "rating": 2.5 },
+                "is_active": true,
+            },
+            "removedFields": ["nickname"],
+            "truncatedArrays": [{ "field": "tags", "newSize": 1 }],
+        })?;
+
+        assert_eq!(
+            json!({
+                "age": { "before": 32, "after": 33 },
+                "project.rating": { "before": 1.5, "after": 2.5 },
+                "is_active": { "before": null, "after": true },
+                "nickname": { "before": "J", "after": null },
+                "tags": { "before": ["a", "b", "c"], "after": ["a"] },
+            }),
+            changes(Some(&before), &update)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes_without_pre_image() -> anyhow::Result<()> {
+        let update: UpdateDescription = from_document(doc! {
+            "updatedFields": { "project.rating": 2.5 },
+            "removedFields": ["nickname"],
+        })?;
+
+        assert_eq!(
+            json!({
+                "project.rating": { "before": null, "after": 2.5 },
+                "nickname": { "before": null, "after": null },
+            }),
+            changes(None, &update)
+        );
+
+        Ok(())
+    }
+}
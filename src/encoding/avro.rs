@@ -27,6 +27,20 @@ pub fn encode(mongo_doc: Document, schema: Schema) -> anyhow::Result<Vec<u8>> {
     Ok(to_avro_datum(&schema, record)?)
 }
 
+/// Returns the top level document fields that are not part of the record schema
+/// and are dropped by the encoding. `_id` is not reported
+pub fn unknown_fields<'a>(mongo_doc: &'a Document, schema: &Schema) -> Vec<&'a str> {
+    let Schema::Record { lookup, .. } = schema else {
+        return Vec::new();
+    };
+
+    mongo_doc
+        .keys()
+        .map(String::as_str)
+        .filter(|key| *key != "_id" && !lookup.contains_key(*key))
+        .collect()
+}
+
 use apache_avro::types::Value as AvroVal;
 use mongodb::bson::Bson;
 
@@ -198,7 +212,7 @@ impl TryFrom<BsonWithSchema> for Wrap {
 
 #[cfg(test)]
 mod tests {
-    use crate::encoding::avro::encode;
+    use crate::encoding::avro::{encode, unknown_fields};
     use anyhow::{bail, Context};
     use apache_avro::{from_avro_datum, Schema};
     use mongodb::bson::{doc, Decimal128};
@@ -299,4 +313,26 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn unknown_fields_of_document() -> anyhow::Result<()> {
+        let schema = Schema::parse_str(
+            r#"{
+                "type": "record",
+                "name": "Employee",
+                "fields": [
+                    { "name": "name", "type": "string" },
+                    { "name": "age", "type": "int" }
+                ]
+            }"#,
+        )?;
+
+        let mongo_doc = doc! { "_id": 1, "name": "Jon", "age": 32, "nickname": "J", "team": "A" };
+        assert_eq!(
+            vec!["nickname", "team"],
+            unknown_fields(&mongo_doc, &schema)
+        );
+
+        Ok(())
+    }
 }
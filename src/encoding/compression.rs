@@ -0,0 +1,45 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+
+use crate::config::Compression;
+
+pub fn compress(payload: &[u8], compression: Compression) -> anyhow::Result<Vec<u8>> {
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(payload)?;
+            Ok(encoder.finish()?)
+        }
+    }
+}
+
+/// Value of the `content_encoding` attribute
+pub fn content_encoding(compression: Compression) -> &'static str {
+    match compression {
+        Compression::Gzip => "gzip",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::compress;
+    use crate::config::Compression;
+
+    #[test]
+    fn gzip_roundtrip() -> anyhow::Result<()> {
+        let payload = b"mstream payload ".repeat(64);
+        let compressed = compress(&payload, Compression::Gzip)?;
+        assert!(compressed.len() < payload.len());
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.as_slice()).read_to_end(&mut decompressed)?;
+        assert_eq!(payload, decompressed);
+
+        Ok(())
+    }
+}
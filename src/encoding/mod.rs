@@ -1 +1,2 @@
 pub mod avro;
+pub mod compression;
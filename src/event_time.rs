@@ -0,0 +1,54 @@
+use mongodb::bson::{Bson, DateTime, Document};
+
+/// Reads the event time from a document field addressed by a dotted path.
+///
+/// Supported bson values:
+///     * datetime
+///     * timestamp       (seconds precision)
+///     * int64           (unix epoch milliseconds)
+///     * int32           (unix epoch seconds, milliseconds don't fit)
+///     * string          (RFC 3339)
+pub fn extract(doc: &Document, path: &str) -> Option<DateTime> {
+    let mut segments = path.split('.');
+    let mut value = doc.get(segments.next()?)?;
+
+    for segment in segments {
+        value = value.as_document()?.get(segment)?;
+    }
+
+    match value {
+        Bson::DateTime(dt) => Some(*dt),
+        Bson::Timestamp(ts) => Some(DateTime::from_millis(ts.time as i64 * 1000)),
+        Bson::Int64(millis) => Some(DateTime::from_millis(*millis)),
+        Bson::Int32(secs) => Some(DateTime::from_millis(*secs as i64 * 1000)),
+        Bson::String(s) => DateTime::parse_rfc3339_str(s).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract;
+    use mongodb::bson::{doc, DateTime, Timestamp};
+
+    #[test]
+    fn extract_supported_types() {
+        let expected = DateTime::from_millis(1_700_000_000_000);
+        let document = doc! {
+            "created_at": expected,
+            "meta": { "updated_at": "2023-11-14T22:13:20Z" },
+            "epoch_millis": 1_700_000_000_000_i64,
+            "epoch_secs": 1_700_000_000_i32,
+            "ts": Timestamp { time: 1_700_000_000, increment: 1 },
+            "name": "Jon",
+        };
+
+        assert_eq!(Some(expected), extract(&document, "created_at"));
+        assert_eq!(Some(expected), extract(&document, "meta.updated_at"));
+        assert_eq!(Some(expected), extract(&document, "epoch_millis"));
+        assert_eq!(Some(expected), extract(&document, "epoch_secs"));
+        assert_eq!(Some(expected), extract(&document, "ts"));
+        assert_eq!(None, extract(&document, "name"));
+        assert_eq!(None, extract(&document, "meta.missing"));
+    }
+}
@@ -1,38 +1,60 @@
+use anyhow::anyhow;
 use gauth::{serv_account::ServiceAccount, token_provider::AsyncTokenProvider};
-use log::{debug, warn};
-use tokio::sync::mpsc;
+use log::{debug, error, warn};
+
+use config::Config;
+use pubsub::GCPTokenProvider;
 
 mod db;
-mod encoding;
+mod delivery;
+mod event_time;
+mod lineage;
+mod notification;
 mod sink;
 
 pub mod cmd;
 pub mod config;
+pub mod daemon;
+pub mod diff;
+pub mod encoding;
+pub mod logging;
 pub mod pubsub;
 pub mod schema;
 
+/// Runs the connectors of the config, a failed connector is stopped while the rest keep running
 pub async fn run_app(config_path: &str) -> anyhow::Result<()> {
     let config = config::Config::load(config_path)?;
-    debug!("config: {:?}", config);
-
-    let worker_count = config.connectors.len();
-    let (tx, mut rx) = mpsc::channel::<String>(worker_count);
+    let tp = token_provider(&config).await;
+    run_streams(config, tp, false).await
+}
 
+/// Creates the token provider and starts refreshing the access token in the background
+/// The refresh can't be stopped, so the provider is created once per process
+pub async fn token_provider(config: &Config) -> AsyncTokenProvider<ServiceAccount> {
     let service_account =
         ServiceAccount::from_file(&config.gcp_serv_acc_key_path, pubsub::SCOPES.to_vec());
 
     let tp = AsyncTokenProvider::new(service_account).with_interval(600);
     tp.watch_updates().await;
+    tp
+}
+
+/// Runs the connectors until all of them exited. With `restart_failed` a failed connector
+/// is restarted from the last event it consumed, the other connectors are not affected
+pub async fn run_streams<TP>(config: Config, tp: TP, restart_failed: bool) -> anyhow::Result<()>
+where
+    TP: GCPTokenProvider + Clone + 'static + Send + Sync,
+{
+    debug!("config: {:?}", config);
 
-    cmd::listener::listen_streams(tx, config, tp).await?;
-    for _ in 0..worker_count {
-        match rx.recv().await {
-            Some(cnt_name) => warn!("stream listener exited: {}", cnt_name),
-            None => warn!("stream listener exited: None"),
+    let mut streams = cmd::listener::listen_streams(config, tp, restart_failed).await?;
+    while let Some(result) = streams.join_next().await {
+        match result {
+            Ok(Ok(())) => warn!("stream listener exited"),
+            Ok(Err(err)) => error!("stream listener failed: {:#}", err),
+            Err(err) => error!("stream listener task failed: {}", err),
         }
     }
 
-    warn!("all stream listeners exited");
-
-    Ok(())
+    Err(anyhow!("all stream listeners exited"))
 }
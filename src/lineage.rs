@@ -0,0 +1,293 @@
+use std::time::Duration;
+
+use apache_avro::schema::{Schema, SchemaKind};
+use log::{debug, error};
+use mongodb::bson::DateTime;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+const PRODUCER: &str = "https://github.com/makarski/mstream";
+const SCHEMA_FACET_URL: &str =
+    "https://openlineage.io/spec/facets/1-1-1/SchemaDatasetFacet.json#/$defs/SchemaDatasetFacet";
+const COLUMN_LINEAGE_FACET_URL: &str =
+    "https://openlineage.io/spec/facets/1-2-0/ColumnLineageDatasetFacet.json#/$defs/ColumnLineageDatasetFacet";
+const JOB_FACET_URL: &str =
+    "https://raw.githubusercontent.com/makarski/mstream/main/spec/MstreamJobFacet.json#/$defs/MstreamJobFacet";
+const NAMESPACE: &str = "mstream";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// LineageEmitter publishes OpenLineage run events describing how a connector
+/// moves fields from mongodb collections to pubsub topics.
+/// An event is sent once the connector resolved its schema at start, schemas are not
+/// refreshed while the connector runs
+#[derive(Clone)]
+pub struct LineageEmitter {
+    client: reqwest::Client,
+    url: String,
+    run_id: Uuid,
+    connector: String,
+    datasets: Vec<LineageDataset>,
+    /// Document fields read by the connector, the schema fields are read without a projection
+    source_fields: Option<Vec<String>>,
+    transformations: Vec<String>,
+}
+
+/// LineageDataset pairs a source collection with the topic its events are published to
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineageDataset {
+    /// `{db}.{collection}`, or `{db}.*` for a database watch
+    pub source: String,
+    /// Topic with the namespace placeholders rendered, unless the collections are unknown
+    pub topic: String,
+}
+
+impl LineageEmitter {
+    pub fn new(
+        url: String,
+        connector: String,
+        datasets: Vec<LineageDataset>,
+        source_fields: Option<Vec<String>>,
+        transformations: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            client,
+            url,
+            run_id: Uuid::new_v4(),
+            connector,
+            datasets,
+            source_fields,
+            transformations,
+        })
+    }
+
+    /// Emits the start event in the background, delivery failures are logged and not retried
+    pub fn emit_start(&self, schema: &Schema) {
+        let request = self
+            .client
+            .post(&self.url)
+            .json(&self.run_event("START", schema));
+        let connector = self.connector.clone();
+
+        tokio::spawn(async move {
+            let result = request
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+
+            match result {
+                Ok(_) => debug!("emitted lineage for connector {}", &connector),
+                Err(err) => error!(
+                    "failed to emit lineage for connector {}: {}",
+                    &connector, err
+                ),
+            }
+        });
+    }
+
+    fn run_event(&self, event_type: &str, schema: &Schema) -> Value {
+        let fields = schema_fields(schema);
+        let field_names: Vec<&str> = fields
+            .iter()
+            .filter_map(|field| field["name"].as_str())
+            .collect();
+        let source_fields: Vec<Value> = match &self.source_fields {
+            Some(names) => names.iter().map(|name| json!({ "name": name })).collect(),
+            None => field_names
+                .iter()
+                .map(|name| json!({ "name": name }))
+                .collect(),
+        };
+
+        let sources = distinct(self.datasets.iter().map(|d| d.source.as_str()));
+        let inputs: Vec<Value> = sources
+            .iter()
+            .map(|source| {
+                json!({
+                    "namespace": "mongodb",
+                    "name": source,
+                    "facets": {
+                        "schema": {
+                            "_producer": PRODUCER,
+                            "_schemaURL": SCHEMA_FACET_URL,
+                            "fields": &source_fields,
+                        }
+                    },
+                })
+            })
+            .collect();
+
+        let topics = distinct(self.datasets.iter().map(|d| d.topic.as_str()));
+        let outputs: Vec<Value> = topics
+            .iter()
+            .map(|topic| {
+                let sources: Vec<&str> = self
+                    .datasets
+                    .iter()
+                    .filter(|d| d.topic == *topic)
+                    .map(|d| d.source.as_str())
+                    .collect();
+
+                json!({
+                    "namespace": "pubsub",
+                    "name": topic,
+                    "facets": {
+                        "schema": {
+                            "_producer": PRODUCER,
+                            "_schemaURL": SCHEMA_FACET_URL,
+                            "fields": &fields,
+                        },
+                        "columnLineage": column_lineage(&field_names, &sources),
+                    },
+                })
+            })
+            .collect();
+
+        json!({
+            "eventType": event_type,
+            "eventTime": DateTime::now().try_to_rfc3339_string().unwrap_or_default(),
+            "producer": PRODUCER,
+            "run": { "runId": self.run_id.to_string() },
+            "job": {
+                "namespace": NAMESPACE,
+                "name": &self.connector,
+                "facets": {
+                    "mstream": {
+                        "_producer": PRODUCER,
+                        "_schemaURL": JOB_FACET_URL,
+                        "transformations": &self.transformations,
+                    }
+                }
+            },
+            "inputs": inputs,
+            "outputs": outputs,
+        })
+    }
+}
+
+fn distinct<'a>(values: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut distinct = Vec::new();
+    for value in values {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
+    }
+    distinct
+}
+
+/// Maps every schema field to the document field of the same name in each source,
+/// documents are encoded by field name
+fn column_lineage(field_names: &[&str], sources: &[&str]) -> Value {
+    let fields: serde_json::Map<String, Value> = field_names
+        .iter()
+        .map(|name| {
+            let input_fields: Vec<Value> = sources
+                .iter()
+                .map(|source| json!({ "namespace": "mongodb", "name": source, "field": name }))
+                .collect();
+
+            let lineage = json!({
+                "inputFields": input_fields,
+                "transformationType": "IDENTITY",
+                "transformationDescription": "avro encoding",
+            });
+            (name.to_string(), lineage)
+        })
+        .collect();
+
+    json!({
+        "_producer": PRODUCER,
+        "_schemaURL": COLUMN_LINEAGE_FACET_URL,
+        "fields": fields,
+    })
+}
+
+/// Lists the record fields with their avro types, nested records are flattened with dotted names
+fn schema_fields(schema: &Schema) -> Vec<Value> {
+    let mut fields = Vec::new();
+    collect_fields("", schema, &mut fields);
+    fields
+}
+
+fn collect_fields(prefix: &str, schema: &Schema, fields: &mut Vec<Value>) {
+    let Schema::Record {
+        fields: record_fields,
+        ..
+    } = schema
+    else {
+        return;
+    };
+
+    for field in record_fields {
+        let name = format!("{}{}", prefix, field.name);
+
+        if let Schema::Record { .. } = field.schema {
+            collect_fields(&format!("{}.", name), &field.schema, fields);
+            continue;
+        }
+
+        let kind = format!("{:?}", SchemaKind::from(&field.schema)).to_lowercase();
+        fields.push(json!({ "name": name, "type": kind }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{column_lineage, schema_fields};
+    use apache_avro::Schema;
+    use serde_json::json;
+
+    #[test]
+    fn schema_fields_flatten_nested_records() -> anyhow::Result<()> {
+        let schema = Schema::parse_str(
+            r###"
+            {
+                "type" : "record",
+                "name" : "Employee",
+                "fields" : [
+                    { "name": "name", "type": "string" },
+                    { "name": "nickname", "type": ["null", "string"] },
+                    { "name": "project", "type": {
+                        "type": "record",
+                        "name": "EmployeeProject",
+                        "fields": [{ "name": "rating", "type": "double" }]
+                    }}
+                ]
+            }
+        "###,
+        )?;
+
+        assert_eq!(
+            vec![
+                json!({ "name": "name", "type": "string" }),
+                json!({ "name": "nickname", "type": "union" }),
+                json!({ "name": "project.rating", "type": "double" }),
+            ],
+            schema_fields(&schema)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn column_lineage_maps_fields_of_every_source() {
+        let lineage = column_lineage(&["name"], &["hr.employees", "hr.contractors"]);
+
+        assert_eq!(
+            json!({
+                "inputFields": [
+                    { "namespace": "mongodb", "name": "hr.employees", "field": "name" },
+                    { "namespace": "mongodb", "name": "hr.contractors", "field": "name" },
+                ],
+                "transformationType": "IDENTITY",
+                "transformationDescription": "avro encoding",
+            }),
+            lineage["fields"]["name"]
+        );
+    }
+}
@@ -1,12 +1,60 @@
+use std::path::PathBuf;
+
 use log::info;
+use mstream::config::{Config, RuntimeCfg};
+use mstream::daemon::{self, DaemonCfg};
+use tokio::runtime::{Builder, Runtime};
 
 const CONFIG_FILE: &str = "mstream-config.toml";
+const PID_FILE: &str = "mstream.pid";
+const CRASH_REPORT_DIR: &str = "crash-reports";
+
+/// Usage: mstream [--config=<path>] [--daemon] [--pid-file=<path>] [--crash-report-dir=<path>]
+fn main() -> anyhow::Result<()> {
+    let mut config_path = CONFIG_FILE.to_owned();
+    let mut daemon_mode = false;
+    let mut pid_file = PathBuf::from(PID_FILE);
+    let mut crash_report_dir = PathBuf::from(CRASH_REPORT_DIR);
+
+    for arg in std::env::args().skip(1) {
+        match arg.split_once('=') {
+            None if arg == "--daemon" => daemon_mode = true,
+            Some(("--config", path)) => config_path = path.to_owned(),
+            Some(("--pid-file", path)) => pid_file = PathBuf::from(path),
+            Some(("--crash-report-dir", path)) => crash_report_dir = PathBuf::from(path),
+            _ => anyhow::bail!("unknown argument: {}", arg),
+        }
+    }
+
+    // logging and the runtime are set up before the app starts, the config is loaded once more by the app
+    let config = Config::load(&config_path)?;
+    mstream::logging::init(&config.logging)?;
+    let runtime = build_runtime(&config.runtime)?;
+
+    if daemon_mode {
+        info!("starting mstream in daemon mode...");
+        return runtime.block_on(daemon::run(DaemonCfg {
+            config_path,
+            pid_file,
+            crash_report_dir,
+        }));
+    }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    pretty_env_logger::try_init_timed()?;
     info!("starting mstream...");
-    mstream::run_app(CONFIG_FILE).await?;
+    runtime.block_on(mstream::run_app(&config_path))
+}
+
+fn build_runtime(cfg: &RuntimeCfg) -> anyhow::Result<Runtime> {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+
+    if let Some(worker_threads) = cfg.worker_threads {
+        builder.worker_threads(worker_threads);
+    }
+
+    if let Some(max_blocking_threads) = cfg.max_blocking_threads {
+        builder.max_blocking_threads(max_blocking_threads);
+    }
 
-    Ok(())
+    Ok(builder.build()?)
 }
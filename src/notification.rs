@@ -0,0 +1,419 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+use serde_json::{json, Value};
+
+use crate::config::{NotificationsCfg, WebhookFormat};
+
+/// JobEvent is a connector lifecycle event that operators are notified about
+#[derive(Debug, Clone)]
+pub enum JobEvent {
+    Failed {
+        connector: String,
+        error: String,
+    },
+    Lagging {
+        connector: String,
+        lag_secs: u64,
+    },
+    Recovered {
+        connector: String,
+        lag_secs: u64,
+    },
+    ErrorRateBreached {
+        connector: String,
+        error_rate: f64,
+    },
+    ErrorRateRecovered {
+        connector: String,
+        error_rate: f64,
+    },
+    SchemaDrift {
+        connector: String,
+        fields: Vec<String>,
+    },
+    EventsSkipped {
+        connector: String,
+        skipped: u64,
+    },
+    RestartLoop {
+        connector: String,
+        restarts: u32,
+    },
+    /// The app is restarted by the daemon, all connectors are affected
+    AppRestarted {
+        restarts: u32,
+        reason: String,
+    },
+}
+
+impl JobEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            JobEvent::Failed { .. } => "failed",
+            JobEvent::Lagging { .. } => "lagging",
+            JobEvent::Recovered { .. } => "recovered",
+            JobEvent::ErrorRateBreached { .. } => "error_rate_breached",
+            JobEvent::ErrorRateRecovered { .. } => "error_rate_recovered",
+            JobEvent::SchemaDrift { .. } => "schema_drift",
+            JobEvent::EventsSkipped { .. } => "events_skipped",
+            JobEvent::RestartLoop { .. } => "restart_loop",
+            JobEvent::AppRestarted { .. } => "app_restarted",
+        }
+    }
+
+    fn connector(&self) -> &str {
+        match self {
+            JobEvent::Failed { connector, .. }
+            | JobEvent::Lagging { connector, .. }
+            | JobEvent::Recovered { connector, .. }
+            | JobEvent::ErrorRateBreached { connector, .. }
+            | JobEvent::ErrorRateRecovered { connector, .. }
+            | JobEvent::SchemaDrift { connector, .. }
+            | JobEvent::EventsSkipped { connector, .. }
+            | JobEvent::RestartLoop { connector, .. } => connector,
+            JobEvent::AppRestarted { .. } => "*",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            JobEvent::Failed { connector, error } => {
+                format!("mstream connector '{}' failed: {}", connector, error)
+            }
+            JobEvent::Lagging {
+                connector,
+                lag_secs,
+            } => format!(
+                "mstream connector '{}' is lagging behind by {}s",
+                connector, lag_secs
+            ),
+            JobEvent::Recovered {
+                connector,
+                lag_secs,
+            } => format!(
+                "mstream connector '{}' caught up, current lag: {}s",
+                connector, lag_secs
+            ),
+            JobEvent::ErrorRateBreached {
+                connector,
+                error_rate,
+            } => format!(
+                "mstream connector '{}' error rate {:.1}% exceeds the threshold",
+                connector,
+                error_rate * 100.0
+            ),
+            JobEvent::ErrorRateRecovered {
+                connector,
+                error_rate,
+            } => format!(
+                "mstream connector '{}' error rate dropped to {:.1}%",
+                connector,
+                error_rate * 100.0
+            ),
+            JobEvent::SchemaDrift { connector, fields } => format!(
+                "mstream connector '{}' received fields unknown to its schema: {}",
+                connector,
+                fields.join(", ")
+            ),
+            JobEvent::EventsSkipped { connector, skipped } => format!(
+                "mstream connector '{}' skipped {} documents that failed to encode",
+                connector, skipped
+            ),
+            JobEvent::RestartLoop {
+                connector,
+                restarts,
+            } => format!(
+                "mstream connector '{}' failed {} times in a row and keeps restarting",
+                connector, restarts
+            ),
+            JobEvent::AppRestarted { restarts, reason } => format!(
+                "mstream crashed and is restarted, restart #{}: {}",
+                restarts, reason
+            ),
+        }
+    }
+
+    fn payload(&self, format: WebhookFormat) -> Value {
+        match format {
+            WebhookFormat::Slack => json!({ "text": self.message() }),
+            WebhookFormat::Generic => json!({
+                "event": self.kind(),
+                "connector": self.connector(),
+                "message": self.message(),
+            }),
+        }
+    }
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Notifier posts job lifecycle events to the configured webhooks
+#[derive(Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    cfg: NotificationsCfg,
+}
+
+impl Notifier {
+    pub fn new(cfg: NotificationsCfg) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self { client, cfg })
+    }
+
+    pub fn lag_threshold_secs(&self) -> Option<u64> {
+        self.cfg.lag_threshold_secs
+    }
+
+    pub fn error_rate_sla(&self) -> Option<ErrorRateSla> {
+        let max = self.cfg.max_error_rate?;
+        let window = self
+            .cfg
+            .error_rate_window
+            .unwrap_or(DEFAULT_ERROR_RATE_WINDOW);
+        Some(ErrorRateSla::new(max, window))
+    }
+
+    /// Delivers the event to every webhook in the background, so that slow webhooks
+    /// don't hold up the stream processing
+    pub fn notify(&self, event: JobEvent) {
+        if self.cfg.webhooks.is_empty() {
+            return;
+        }
+
+        let notifier = self.clone();
+        tokio::spawn(async move { notifier.deliver(event).await });
+    }
+
+    /// Delivers the event to every webhook. Delivery failures are logged and never
+    /// interrupt the stream processing
+    pub async fn deliver(&self, event: JobEvent) {
+        for webhook in self.cfg.webhooks.iter() {
+            let result = self
+                .client
+                .post(&webhook.url)
+                .json(&event.payload(webhook.format))
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+
+            match result {
+                Ok(_) => debug!("sent '{}' notification to webhook", event.kind()),
+                Err(err) => error!(
+                    "failed to send '{}' notification for connector {}: {}",
+                    event.kind(),
+                    event.connector(),
+                    err
+                ),
+            }
+        }
+    }
+}
+
+const DEFAULT_ERROR_RATE_WINDOW: usize = 100;
+
+/// ErrorRateSla tracks the share of failed events over the most recent events
+/// and reports when it crosses the threshold
+pub struct ErrorRateSla {
+    max: f64,
+    window: usize,
+    outcomes: VecDeque<bool>,
+    failures: usize,
+    breached: bool,
+}
+
+impl ErrorRateSla {
+    fn new(max: f64, window: usize) -> Self {
+        Self {
+            max,
+            window: window.max(1),
+            outcomes: VecDeque::with_capacity(window),
+            failures: 0,
+            breached: false,
+        }
+    }
+
+    /// Records the outcome of an event. Returns the breach or recovery event
+    /// once the window is filled and the error rate crossed the threshold
+    pub fn record(&mut self, connector: &str, failed: bool) -> Option<JobEvent> {
+        self.outcomes.push_back(failed);
+        self.failures += failed as usize;
+        if self.outcomes.len() > self.window {
+            let dropped = self.outcomes.pop_front().unwrap_or_default();
+            self.failures -= dropped as usize;
+        }
+
+        if self.outcomes.len() < self.window {
+            return None;
+        }
+
+        let error_rate = self.failures as f64 / self.window as f64;
+        let connector = connector.to_owned();
+
+        if error_rate > self.max && !self.breached {
+            self.breached = true;
+            Some(JobEvent::ErrorRateBreached {
+                connector,
+                error_rate,
+            })
+        } else if error_rate <= self.max && self.breached {
+            self.breached = false;
+            Some(JobEvent::ErrorRateRecovered {
+                connector,
+                error_rate,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Max number of unknown fields tracked per connector, further fields are not reported
+const MAX_UNKNOWN_FIELDS: usize = 1000;
+const SCHEMA_DRIFT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// SchemaDrift remembers the fields unknown to the connector schema and batches
+/// their notifications, at most one notification is sent per interval
+pub struct SchemaDrift {
+    known: HashSet<String>,
+    pending: Vec<String>,
+    interval: Duration,
+    notified_at: Option<Instant>,
+}
+
+impl SchemaDrift {
+    pub fn new() -> Self {
+        Self::with_interval(SCHEMA_DRIFT_INTERVAL)
+    }
+
+    fn with_interval(interval: Duration) -> Self {
+        Self {
+            known: HashSet::new(),
+            pending: Vec::new(),
+            interval,
+            notified_at: None,
+        }
+    }
+
+    /// Records the unknown fields of a document. Returns the fields seen for the first time,
+    /// nothing once the tracked fields reached the limit
+    pub fn record<'a>(&mut self, fields: Vec<&'a str>) -> Vec<&'a str> {
+        let room = MAX_UNKNOWN_FIELDS - self.known.len();
+        let new_fields: Vec<&str> = fields
+            .into_iter()
+            .filter(|field| !self.known.contains(*field))
+            .take(room)
+            .collect();
+
+        self.known
+            .extend(new_fields.iter().map(|field| field.to_string()));
+        self.pending
+            .extend(new_fields.iter().map(|field| field.to_string()));
+        new_fields
+    }
+
+    /// Returns the notification of the pending fields once the interval passed since the last one
+    pub fn event(&mut self, connector: &str) -> Option<JobEvent> {
+        if self.pending.is_empty()
+            || self
+                .notified_at
+                .is_some_and(|notified_at| notified_at.elapsed() < self.interval)
+        {
+            return None;
+        }
+
+        self.notified_at = Some(Instant::now());
+        Some(JobEvent::SchemaDrift {
+            connector: connector.to_owned(),
+            fields: std::mem::take(&mut self.pending),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorRateSla, JobEvent, SchemaDrift, MAX_UNKNOWN_FIELDS};
+    use crate::config::WebhookFormat;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn payload_formats() {
+        let event = JobEvent::Failed {
+            connector: "employees".to_owned(),
+            error: "boom".to_owned(),
+        };
+
+        assert_eq!(
+            json!({ "text": "mstream connector 'employees' failed: boom" }),
+            event.payload(WebhookFormat::Slack)
+        );
+        assert_eq!(
+            json!({
+                "event": "failed",
+                "connector": "employees",
+                "message": "mstream connector 'employees' failed: boom",
+            }),
+            event.payload(WebhookFormat::Generic)
+        );
+    }
+
+    #[test]
+    fn error_rate_breach_and_recovery() {
+        let mut sla = ErrorRateSla::new(0.25, 4);
+
+        assert!(sla.record("employees", true).is_none());
+        assert!(sla.record("employees", false).is_none());
+        assert!(sla.record("employees", false).is_none());
+        assert!(matches!(
+            sla.record("employees", true),
+            Some(JobEvent::ErrorRateBreached { error_rate, .. }) if error_rate == 0.5
+        ));
+        assert!(sla.record("employees", true).is_none());
+        assert!(sla.record("employees", false).is_none());
+        assert!(sla.record("employees", false).is_none());
+        assert!(matches!(
+            sla.record("employees", false),
+            Some(JobEvent::ErrorRateRecovered { error_rate, .. }) if error_rate == 0.25
+        ));
+    }
+
+    #[test]
+    fn schema_drift_batches_notifications() {
+        let mut drift = SchemaDrift::with_interval(Duration::from_secs(3600));
+
+        assert_eq!(vec!["nickname"], drift.record(vec!["nickname"]));
+        assert!(matches!(
+            drift.event("employees"),
+            Some(JobEvent::SchemaDrift { fields, .. }) if fields == ["nickname"]
+        ));
+
+        // notified again once the interval passed
+        assert_eq!(vec!["team"], drift.record(vec!["nickname", "team"]));
+        assert!(drift.event("employees").is_none());
+        drift.interval = Duration::ZERO;
+        assert!(matches!(
+            drift.event("employees"),
+            Some(JobEvent::SchemaDrift { fields, .. }) if fields == ["team"]
+        ));
+        assert!(drift.event("employees").is_none());
+    }
+
+    #[test]
+    fn schema_drift_tracks_limited_fields() {
+        let mut drift = SchemaDrift::with_interval(Duration::ZERO);
+        let fields: Vec<String> = (0..MAX_UNKNOWN_FIELDS + 10)
+            .map(|i| format!("field_{}", i))
+            .collect();
+
+        let new_fields = drift.record(fields.iter().map(String::as_str).collect());
+        assert_eq!(MAX_UNKNOWN_FIELDS, new_fields.len());
+        assert!(drift.record(vec!["another_field"]).is_empty());
+    }
+}
@@ -4,12 +4,15 @@ use tonic::service::{interceptor::InterceptedService, Interceptor};
 use tonic::transport::{Channel, ClientTlsConfig};
 use tonic::{Code, Request, Status};
 
+// generated code
+#[allow(clippy::all)]
 pub mod api {
     include!("api/google.pubsub.v1.rs");
 }
 pub mod srvc;
 
-const ENDPOINT: &str = "https://pubsub.googleapis.com";
+/// Global pubsub endpoint, regional endpoints follow the `https://{region}-pubsub.googleapis.com` format
+pub const ENDPOINT: &str = "https://pubsub.googleapis.com";
 pub const SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/pubsub"];
 
 #[derive(Clone, Debug)]
@@ -48,14 +51,15 @@ impl<P: GCPTokenProvider + Clone> Interceptor for ServiceAccountAuth<P> {
     }
 }
 
-pub async fn tls_transport() -> anyhow::Result<Channel> {
+pub async fn tls_transport(endpoint: &str) -> anyhow::Result<Channel> {
     let tls_config = ClientTlsConfig::new();
 
-    let channel = Channel::from_static(ENDPOINT)
+    let channel = Channel::from_shared(endpoint.to_owned())
+        .map_err(|err| anyhow!("invalid pubsub endpoint '{}': {}", endpoint, err))?
         .tls_config(tls_config)?
         .connect()
         .await
-        .map_err(|err| anyhow!("failed to initiate tls_transport: {}", err))?;
+        .map_err(|err| anyhow!("failed to initiate tls_transport to {}: {}", endpoint, err))?;
 
     Ok(channel)
 }
@@ -1,15 +1,15 @@
 use std::collections::HashMap;
 
 use anyhow::{anyhow, Ok};
-use apache_avro::Schema;
+use apache_avro::{rabin::Rabin, Schema};
 use async_trait::async_trait;
 use tonic::service::Interceptor;
 
-use super::{tls_transport, Channel, InterceptedService};
+use super::{tls_transport, Channel, InterceptedService, ENDPOINT};
 use crate::pubsub::api::publisher_client::PublisherClient;
 use crate::pubsub::api::schema_service_client::SchemaServiceClient;
 use crate::pubsub::api::{GetSchemaRequest, ListSchemasRequest, ListSchemasResponse};
-use crate::pubsub::api::{PublishRequest, PubsubMessage};
+use crate::pubsub::api::{GetTopicRequest, PublishRequest, PubsubMessage, SchemaSettings};
 use crate::schema::SchemaProvider;
 use crate::sink::EventSink;
 
@@ -19,11 +19,31 @@ pub struct PubSubPublisher<I> {
 
 impl<I: Interceptor> PubSubPublisher<I> {
     pub async fn with_interceptor(interceptor: I) -> anyhow::Result<Self> {
-        let channel = tls_transport().await?;
+        Self::with_endpoint(ENDPOINT, interceptor).await
+    }
+
+    pub async fn with_endpoint(endpoint: &str, interceptor: I) -> anyhow::Result<Self> {
+        let channel = tls_transport(endpoint).await?;
         Ok(Self {
             client: PublisherClient::with_interceptor(channel, interceptor),
         })
     }
+
+    /// Returns the schema settings attached to the topic, if any
+    pub async fn topic_schema_settings(
+        &mut self,
+        topic: String,
+    ) -> anyhow::Result<Option<SchemaSettings>> {
+        let topic_response = self
+            .client
+            .get_topic(GetTopicRequest {
+                topic: topic.clone(),
+            })
+            .await
+            .map_err(|err| anyhow!("{}. topic: {}", err.message(), &topic))?;
+
+        Ok(topic_response.into_inner().schema_settings)
+    }
 }
 
 #[async_trait]
@@ -57,16 +77,22 @@ impl<I: Interceptor + Send> EventSink for PubSubPublisher<I> {
 pub struct SchemaService<I> {
     client: SchemaServiceClient<InterceptedService<Channel, I>>,
     cache: HashMap<String, Schema>,
+    revisions: HashMap<String, String>,
 }
 
 impl<I: Interceptor> SchemaService<I> {
     pub async fn with_interceptor(interceptor: I) -> anyhow::Result<Self> {
-        let channel = tls_transport().await?;
+        Self::with_endpoint(ENDPOINT, interceptor).await
+    }
+
+    pub async fn with_endpoint(endpoint: &str, interceptor: I) -> anyhow::Result<Self> {
+        let channel = tls_transport(endpoint).await?;
         let client = SchemaServiceClient::with_interceptor(channel, interceptor);
 
         Ok(Self {
             client,
             cache: HashMap::new(),
+            revisions: HashMap::new(),
         })
     }
 
@@ -95,6 +121,8 @@ impl<I: Interceptor + Send> SchemaProvider for SchemaService<I> {
             let pubsub_schema = schema_response.await?.into_inner();
             let avro_schema = Schema::parse_str(&pubsub_schema.definition)?;
             self.cache.insert(id.clone(), avro_schema);
+            self.revisions
+                .insert(id.clone(), pubsub_schema.revision_id.clone());
 
             log::info!("schema {} added to cache", id);
         } else {
@@ -106,4 +134,12 @@ impl<I: Interceptor + Send> SchemaProvider for SchemaService<I> {
             .cloned()
             .ok_or_else(|| anyhow!("schema not found"))
     }
+
+    /// The revision id of the pubsub schema
+    fn schema_version(&self, id: &str, schema: &Schema) -> String {
+        match self.revisions.get(id) {
+            Some(revision_id) if !revision_id.is_empty() => revision_id.clone(),
+            _ => schema.fingerprint::<Rabin>().to_string(),
+        }
+    }
 }
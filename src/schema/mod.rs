@@ -1,10 +1,12 @@
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Ok};
-use apache_avro::Schema;
+use apache_avro::{rabin::Rabin, Schema};
 use async_trait::async_trait;
 use mongodb::bson::doc;
 use serde::{Deserialize, Serialize};
 
-const SCHEMA_REGISTRY_COLLECTION: &str = "mstream_schemas";
+pub const SCHEMA_REGISTRY_COLLECTION: &str = "mstream_schemas";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchemaEntry {
@@ -22,30 +24,48 @@ enum SchemaEncoding {
 #[async_trait]
 pub trait SchemaProvider {
     async fn get_schema(&mut self, id: String) -> anyhow::Result<Schema>;
+
+    /// Version of a fetched schema that is stamped on the messages.
+    /// The avro Rabin fingerprint of the canonical form, unless the registry tracks revisions
+    fn schema_version(&self, _id: &str, schema: &Schema) -> String {
+        schema.fingerprint::<Rabin>().to_string()
+    }
 }
 
 pub struct MongoDbSchemaProvider {
     db: mongodb::Database,
+    cache: HashMap<String, Schema>,
 }
 
 impl MongoDbSchemaProvider {
     pub async fn new(db: mongodb::Database) -> Self {
-        Self { db }
+        Self {
+            db,
+            cache: HashMap::new(),
+        }
     }
 }
 
 #[async_trait]
 impl SchemaProvider for MongoDbSchemaProvider {
     async fn get_schema(&mut self, id: String) -> anyhow::Result<Schema> {
+        if let Some(schema) = self.cache.get(&id) {
+            return Ok(schema.clone());
+        }
+
         let collection = self
             .db
             .collection::<SchemaEntry>(SCHEMA_REGISTRY_COLLECTION);
 
-        let schema = collection
+        let entry = collection
             .find_one(doc! {"schema_id": &id}, None)
             .await?
             .ok_or_else(|| anyhow!("schema not found: {}", id))?;
 
-        Ok(Schema::parse_str(&schema.schema_definition)?)
+        let schema = Schema::parse_str(&entry.schema_definition)?;
+        self.cache.insert(id.clone(), schema.clone());
+        log::info!("schema {} added to cache", id);
+
+        Ok(schema)
     }
 }
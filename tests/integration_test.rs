@@ -2,7 +2,6 @@ use std::collections::HashMap;
 
 use log::{debug, info};
 use mongodb::{bson::doc, options::UpdateOptions, Client, Collection};
-use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
 
 mod setup;
@@ -25,8 +24,7 @@ async fn test_created_updated_db_to_pubsub() {
     let coll = db.collection(setup::DB_COLLECTION);
 
     // spawn change stream listener
-    let (tx, _) = mpsc::channel::<String>(1);
-    start_app_listener(tx).await;
+    start_app_listener().await;
 
     info!("setting up db, sleeping for 10 secs");
     sleep(Duration::from_secs(10)).await;
@@ -101,7 +99,7 @@ async fn assert_employees_eq_pubsub(
         events.extend(events2);
     }
 
-    events.sort_by(|a, b| a.1.id.cmp(&b.1.id));
+    events.sort_by_key(|a| a.1.id);
 
     for (i, (attributes, event)) in events.into_iter().enumerate() {
         debug!("{:?}", event);
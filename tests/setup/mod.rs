@@ -6,10 +6,9 @@ use apache_avro::AvroSchema;
 use mongodb::bson::{doc, Document};
 use mongodb::Collection;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
 use tonic::service::Interceptor;
 
-use mstream::config::{SchemaCfg, SchemaProviderName};
+use mstream::config::{CollectionSelector, SchemaCfg, SchemaProviderName};
 use mstream::pubsub::api::{AcknowledgeRequest, PullRequest};
 use mstream::pubsub::{GCPTokenProvider, ServiceAccountAuth};
 
@@ -29,7 +28,7 @@ pub struct Employee {
     pub rating: f64,
 }
 
-pub async fn start_app_listener(done_ch: mpsc::Sender<String>) {
+pub async fn start_app_listener() {
     use mstream::cmd::listener;
     use mstream::config::{Config, Connector};
 
@@ -39,19 +38,40 @@ pub async fn start_app_listener(done_ch: mpsc::Sender<String>) {
                 name: CONNECTOR_NAME.to_owned(),
                 db_connection: DB_CONNECTION.to_owned(),
                 db_name: DB_NAME.to_owned(),
-                db_collection: DB_COLLECTION.to_owned(),
+                db_collection: CollectionSelector::Name(DB_COLLECTION.to_owned()),
                 schema: SchemaCfg {
                     provider: SchemaProviderName::Gcp,
                     id: env::var("PUBSUB_SCHEMA").unwrap(),
                 },
+                fields: None,
                 topic: env::var("PUBSUB_TOPIC").unwrap(),
+                attributes: Default::default(),
+                required_attributes: vec![],
+                validate_topic_schema: false,
+                pubsub_endpoint: None,
+                delivery_log: None,
+                compression: None,
+                on_encode_error: Default::default(),
+                skip_delivered: false,
+                update_diff: None,
+                pipeline: Vec::new(),
+                polling: Default::default(),
+                event_time: None,
+                fallback: None,
+                poll_fallback: None,
+                schedule: None,
+                mongodb: Default::default(),
+                notifications: None,
             }],
             ..Default::default()
         };
 
         let tp = AccessToken::init().unwrap();
 
-        listener::listen_streams(done_ch, config, tp).await.unwrap();
+        let mut streams = listener::listen_streams(config, tp, false).await.unwrap();
+        while let Some(result) = streams.join_next().await {
+            result.unwrap().unwrap();
+        }
     });
 }
 
@@ -99,8 +119,8 @@ use tonic::transport::Channel;
 type SubscriberService<I> = SubscriberClient<InterceptedService<Channel, I>>;
 
 async fn subscriber<I: Interceptor>(interceptor: I) -> anyhow::Result<SubscriberService<I>> {
-    use mstream::pubsub::tls_transport;
-    let channel = tls_transport().await?;
+    use mstream::pubsub::{tls_transport, ENDPOINT};
+    let channel = tls_transport(ENDPOINT).await?;
     Ok(SubscriberClient::with_interceptor(channel, interceptor))
 }
 